@@ -1,7 +1,11 @@
 use sw_composite::*;
 
-use crate::{IntPoint, Point, Transform};
-use crate::draw_target::{ExtendMode, Source, FilterMode};
+use crate::{IntPoint, IntRect, Point, Transform};
+use crate::draw_target::{ExtendMode, Source, SourceWithOptions, FilterMode};
+use crate::format::{f32_to_argb32, Argb32, PixelFormat};
+use crate::gradient::GradientF32;
+use crate::mesh::MeshShader;
+use crate::wide::row_to_argb32;
 
 use euclid::vec2;
 use std::marker::PhantomData;
@@ -480,128 +484,140 @@ impl Shader for LinearGradientShader {
     }
 }
 
-pub struct ShaderMaskBlitter<'a> {
+/// Evaluates a gradient at each pixel with f32 precision instead of using a lookup table
+/// and optionally dithers the result.
+pub struct GradientF32Shader {
+    gradient: GradientF32,
+    dither: bool,
+}
+
+impl Shader for GradientF32Shader {
+    fn shade_span(&self, x: i32, y: i32, dest: &mut [u32], count: usize) {
+        let mut tmp = [[0.; 4]; 64];
+        for (i, dest) in dest[..count].chunks_mut(tmp.len()).enumerate() {
+            let x = x + (i * tmp.len()) as i32;
+            let tmp = &mut tmp[..dest.len()];
+            self.gradient.shade_span(x, y, tmp);
+            row_to_argb32(tmp, dest, x, y, self.dither);
+        }
+    }
+}
+
+// The shader blitters convert the pixels of each span of `dest` to premultiplied ARGB32 in
+// `row` to draw to them when `F` stores pixels some other way. The mask blitters, which
+// draw with SrcOver, leave that to `PixelFormat::over_in` so formats can blend directly.
+
+pub struct ShaderMaskBlitter<'a, F: PixelFormat = Argb32> {
     pub x: i32,
     pub y: i32,
     pub shader: &'a dyn Shader,
     pub tmp: Vec<u32>,
-    pub dest: &'a mut [u32],
+    pub dest: &'a mut [F::Pixel],
     pub dest_stride: i32,
+    pub row: Vec<u32>,
 }
 
-impl<'a> Blitter for ShaderMaskBlitter<'a> {
+impl<'a, F: PixelFormat> Blitter for ShaderMaskBlitter<'a, F> {
     fn blit_span(&mut self, y: i32, x1: i32, x2: i32, mask: &[u8]) {
-        let dest_row = (y - self.y) * self.dest_stride;
+        let dest_start = ((y - self.y) * self.dest_stride + x1 - self.x) as usize;
         let count = (x2 - x1) as usize;
         self.shader.shade_span(x1, y, &mut self.tmp[..], count);
-        for i in 0..count {
-            let mask = mask[i] as u32;
-            if mask != 0 {
-                self.dest[(dest_row + x1 - self.x) as usize + i] = over_in(
-                    self.tmp[i],
-                    self.dest[(dest_row + x1 - self.x) as usize + i],
-                    mask,
-                );
-            }
-        }
+        F::over_in(&self.tmp[..count], mask, None, &mut self.dest[dest_start..dest_start + count], &mut self.row);
     }
 }
 
-pub struct ShaderClipMaskBlitter<'a> {
+pub struct ShaderClipMaskBlitter<'a, F: PixelFormat = Argb32> {
     pub x: i32,
     pub y: i32,
     pub shader: &'a dyn Shader,
     pub tmp: Vec<u32>,
-    pub dest: &'a mut [u32],
+    pub dest: &'a mut [F::Pixel],
     pub dest_stride: i32,
     pub clip: &'a [u8],
     pub clip_stride: i32,
+    pub row: Vec<u32>,
 }
 
-impl<'a> Blitter for ShaderClipMaskBlitter<'a> {
+impl<'a, F: PixelFormat> Blitter for ShaderClipMaskBlitter<'a, F> {
     fn blit_span(&mut self, y: i32, x1: i32, x2: i32, mask: &[u8]) {
-        let dest_row = (y - self.y) * self.dest_stride;
-        let clip_row = y * self.clip_stride;
+        let dest_start = ((y - self.y) * self.dest_stride + x1 - self.x) as usize;
+        let clip_start = (y * self.clip_stride + x1) as usize;
         let count = (x2 - x1) as usize;
         self.shader.shade_span(x1, y, &mut self.tmp[..], count);
-        for i in 0..count {
-            let mask = mask[i] as u32;
-            let clip = self.clip[(clip_row + x1) as usize + i] as u32;
-            if mask != 0 && clip != 0 {
-                self.dest[(dest_row + x1 - self.x) as usize + i] = over_in_in(
-                    self.tmp[i],
-                    self.dest[(dest_row + x1 - self.x) as usize + i],
-                    mask,
-                    clip,
-                );
-            }
-        }
+        let clip = &self.clip[clip_start..clip_start + count];
+        F::over_in(&self.tmp[..count], mask, Some(clip), &mut self.dest[dest_start..dest_start + count], &mut self.row);
     }
 }
 
-pub struct ShaderClipBlendMaskBlitter<'a> {
+pub struct ShaderClipBlendMaskBlitter<'a, F: PixelFormat = Argb32> {
     pub x: i32,
     pub y: i32,
     pub shader: &'a dyn Shader,
     pub tmp: Vec<u32>,
-    pub dest: &'a mut [u32],
+    pub dest: &'a mut [F::Pixel],
     pub dest_stride: i32,
     pub clip: &'a [u8],
     pub clip_stride: i32,
     pub blend_fn: fn (&[u32], &[u8], &[u8], &mut [u32]),
+    pub row: Vec<u32>,
 }
 
-impl<'a> Blitter for ShaderClipBlendMaskBlitter<'a> {
+impl<'a, F: PixelFormat> Blitter for ShaderClipBlendMaskBlitter<'a, F> {
     fn blit_span(&mut self, y: i32, x1: i32, x2: i32, mask: &[u8]) {
-        let dest_row = (y - self.y) * self.dest_stride;
-        let clip_row = y * self.clip_stride;
+        let dest_start = ((y - self.y) * self.dest_stride + x1 - self.x) as usize;
+        let clip_start = (y * self.clip_stride + x1) as usize;
         let count = (x2 - x1) as usize;
         self.shader.shade_span(x1, y, &mut self.tmp[..], count);
-        (self.blend_fn)(&self.tmp[..],
-                      mask,
-                      &self.clip[(clip_row + x1) as usize..],
-                      &mut self.dest[(dest_row + x1 - self.x) as usize..])
+        let (tmp, clip, blend_fn) = (&self.tmp[..count], &self.clip[clip_start..], self.blend_fn);
+        F::with_argb32(&mut self.dest[dest_start..dest_start + count], &mut self.row, |dest| {
+            blend_fn(tmp, mask, clip, dest)
+        });
     }
 }
 
-pub struct ShaderBlendMaskBlitter<'a> {
+pub struct ShaderBlendMaskBlitter<'a, F: PixelFormat = Argb32> {
     pub x: i32,
     pub y: i32,
     pub shader: &'a dyn Shader,
     pub tmp: Vec<u32>,
-    pub dest: &'a mut [u32],
+    pub dest: &'a mut [F::Pixel],
     pub dest_stride: i32,
     pub blend_fn: fn (&[u32], &[u8], &mut [u32]),
+    pub row: Vec<u32>,
 }
 
-impl<'a> Blitter for ShaderBlendMaskBlitter<'a> {
+impl<'a, F: PixelFormat> Blitter for ShaderBlendMaskBlitter<'a, F> {
     fn blit_span(&mut self, y: i32, x1: i32, x2: i32, mask: &[u8]) {
-        let dest_row = (y - self.y) * self.dest_stride;
+        let dest_start = ((y - self.y) * self.dest_stride + x1 - self.x) as usize;
         let count = (x2 - x1) as usize;
         self.shader.shade_span(x1, y, &mut self.tmp[..], count);
-        (self.blend_fn)(&self.tmp[..],
-                        mask,
-                        &mut self.dest[(dest_row + x1 - self.x) as usize..])
+        let (tmp, blend_fn) = (&self.tmp[..count], self.blend_fn);
+        F::with_argb32(&mut self.dest[dest_start..dest_start + count], &mut self.row, |dest| {
+            blend_fn(tmp, mask, dest)
+        });
     }
 }
 
-pub struct ShaderBlendBlitter<'a> {
+pub struct ShaderBlendBlitter<'a, F: PixelFormat = Argb32> {
     pub x: i32,
     pub y: i32,
     pub shader: &'a dyn Shader,
     pub tmp: Vec<u32>,
-    pub dest: &'a mut [u32],
+    pub dest: &'a mut [F::Pixel],
     pub dest_stride: i32,
     pub blend_fn: fn (&[u32], &mut [u32]),
+    pub row: Vec<u32>,
 }
 
-impl<'a> Blitter for ShaderBlendBlitter<'a> {
+impl<'a, F: PixelFormat> Blitter for ShaderBlendBlitter<'a, F> {
     fn blit_span(&mut self, y: i32, x1: i32, x2: i32, _: &[u8]) {
-        let dest_row = (y - self.y) * self.dest_stride;
+        let dest_start = ((y - self.y) * self.dest_stride + x1 - self.x) as usize;
         let count = (x2 - x1) as usize;
         self.shader.shade_span(x1, y, &mut self.tmp[..], count);
-        (self.blend_fn)(&self.tmp[..],
-                        &mut self.dest[(dest_row + x1 - self.x) as usize..])
+        let (tmp, blend_fn) = (&self.tmp[..count], self.blend_fn);
+        F::with_argb32(&mut self.dest[dest_start..dest_start + count], &mut self.row, |dest| {
+            blend_fn(tmp, dest)
+        });
     }
 }
 
@@ -638,12 +654,44 @@ pub enum ShaderStorage<'a, 'b> {
     TwoCircleRadialGradient(TwoCircleRadialGradientShader),
     LinearGradient(LinearGradientShader),
     SweepGradient(SweepGradientShader),
+    GradientF32(GradientF32Shader),
+    Mesh(MeshShader),
+}
+
+// Builds the shader for the gradient source `src` using the stops of `gradient`
+fn gradient_shader<'a, 'b>(ti: &Transform, src: &Source, gradient: &Gradient, alpha: u32) -> ShaderStorage<'a, 'b> {
+    match *src {
+        Source::RadialGradient(_, spread, transform) => {
+            let s = RadialGradientShader::new(gradient, &ti.then(&transform), spread, alpha);
+            ShaderStorage::RadialGradient(s)
+        }
+        Source::TwoCircleRadialGradient(_, spread, c1, r1, c2, r2, transform) => {
+            let s = TwoCircleRadialGradientShader::new(gradient, &ti.then(&transform), c1, r1, c2, r2, spread, alpha);
+            ShaderStorage::TwoCircleRadialGradient(s)
+        }
+        Source::SweepGradient(_, spread, start_angle, end_angle, transform) => {
+            let s = SweepGradientShader::new(gradient, &ti.then(&transform), start_angle, end_angle, spread, alpha);
+            ShaderStorage::SweepGradient(s)
+        }
+        Source::LinearGradient(_, spread, transform) => {
+            let s = LinearGradientShader::new(gradient, &ti.then(&transform), spread, alpha);
+            ShaderStorage::LinearGradient(s)
+        }
+        _ => unreachable!(),
+    }
 }
 
 // The idea here is to store a shader in shader_storage and then return
 // a reference to it. The goal is to avoid a heap allocation but the end
-// result is pretty ugly.
-pub fn choose_shader<'a, 'b, 'c>(ti: &Transform, src: &'b Source<'c>, alpha: f32, shader_storage: &'a mut ShaderStorage<'b, 'c>) -> &'a dyn Shader {
+// result is pretty ugly. `rect` is the part of the target that will be shaded.
+pub fn choose_shader<'a, 'b, 'c>(ti: &Transform, src: &'b Source<'c>, alpha: f32, rect: IntRect, shader_storage: &'a mut ShaderStorage<'b, 'c>) -> &'a dyn Shader {
+    if let Source::GradientWithOptions(ref with_options) = *src {
+        if with_options.source.gradient().is_none() {
+            // the options only apply to gradients
+            return choose_shader(ti, &with_options.source, alpha, rect, shader_storage);
+        }
+    }
+
     // XXX: clamp alpha
     let alpha = (alpha * 255. + 0.5) as u32;
 
@@ -653,6 +701,11 @@ pub fn choose_shader<'a, 'b, 'c>(ti: &Transform, src: &'b Source<'c>, alpha: f32
             let s = SolidShader { color };
             ShaderStorage::Solid(s)
         }
+        Source::SolidF32(c) => {
+            let color = f32_to_argb32([c.r, c.g, c.b, c.a], 0.5);
+            let s = SolidShader { color: alpha_mul(color, alpha_to_alpha256(alpha)) };
+            ShaderStorage::Solid(s)
+        }
         Source::Image(ref image, ExtendMode::Pad, filter, transform) => {
             if let Some(offset) = is_integer_transform(&ti.then(&transform)) {
                 ShaderStorage::ImagePadAlpha(ImagePadAlphaShader::new(image, offset.x, offset.y, alpha))
@@ -699,51 +752,61 @@ pub fn choose_shader<'a, 'b, 'c>(ti: &Transform, src: &'b Source<'c>, alpha: f32
                 }
             }
         }
-        Source::RadialGradient(ref gradient, spread, transform) => {
-            let s = RadialGradientShader::new(gradient, &ti.then(&transform), *spread, alpha);
-            ShaderStorage::RadialGradient(s)
-        }
-        Source::TwoCircleRadialGradient(ref gradient, spread, c1, r1, c2, r2, transform) => {
-            let s = TwoCircleRadialGradientShader::new(gradient, &ti.then(&transform), *c1, *r1, *c2, *r2, *spread, alpha);
-            ShaderStorage::TwoCircleRadialGradient(s)
-        }
-        Source::SweepGradient(ref gradient, spread, start_angle, end_angle, transform) => {
-            let s = SweepGradientShader::new(gradient, &ti.then(&transform), *start_angle, *end_angle, *spread, alpha);
-            ShaderStorage::SweepGradient(s)
+        Source::RadialGradient(ref gradient, ..) |
+        Source::TwoCircleRadialGradient(ref gradient, ..) |
+        Source::SweepGradient(ref gradient, ..) |
+        Source::LinearGradient(ref gradient, ..) => gradient_shader(ti, src, gradient, alpha),
+        Source::GradientWithOptions(ref with_options) => {
+            let SourceWithOptions { source, options, resampled } = &**with_options;
+            if !options.uses_lookup_table() {
+                let gradient = GradientF32::new(src, ti, alpha as f32 / 255.).unwrap();
+                ShaderStorage::GradientF32(GradientF32Shader { gradient, dither: options.dither })
+            } else {
+                // gradient() is checked above and the stops are only resampled for color spaces other than sRGB
+                let gradient = resampled.as_ref().or(source.gradient()).unwrap();
+                gradient_shader(ti, source, gradient, alpha)
+            }
         }
-        Source::LinearGradient(ref gradient, spread, transform) => {
-            let s = LinearGradientShader::new(gradient, &ti.then(&transform), *spread, alpha);
-            ShaderStorage::LinearGradient(s)
+        Source::MeshGradient(mesh, transform) => {
+            ShaderStorage::Mesh(MeshShader::new(mesh, &ti.then(transform), alpha as f32 / 255., rect))
         }
     };
 
-    match shader_storage {
-        ShaderStorage::None => unreachable!(),
-        ShaderStorage::Solid(s) => s,
-        ShaderStorage::ImagePadAlpha(s) => s,
-        ShaderStorage::ImageRepeatAlpha(s) => s,
-        ShaderStorage::TransformedNearestPadImageAlpha(s) => s,
-        ShaderStorage::TransformedNearestRepeatImageAlpha(s) => s,
-        ShaderStorage::TransformedPadImageAlpha(s) => s,
-        ShaderStorage::TransformedRepeatImageAlpha(s) => s,
-        ShaderStorage::TransformedPadImage(s) => s,
-        ShaderStorage::TransformedRepeatImage(s) => s,
-        ShaderStorage::TransformedNearestPadImage(s) => s,
-        ShaderStorage::TransformedNearestRepeatImage(s) => s,
-        ShaderStorage::RadialGradient(s) => s,
-        ShaderStorage::TwoCircleRadialGradient(s) => s,
-        ShaderStorage::SweepGradient(s) => s,
-        ShaderStorage::LinearGradient(s) => s,
-    }
-}
-
-pub enum ShaderBlitterStorage<'a> {
+    shader_storage.shader()
+}
+
+impl<'a, 'b> ShaderStorage<'a, 'b> {
+    pub fn shader(&self) -> &dyn Shader {
+        match self {
+            ShaderStorage::None => unreachable!(),
+            ShaderStorage::Solid(s) => s,
+            ShaderStorage::ImagePadAlpha(s) => s,
+            ShaderStorage::ImageRepeatAlpha(s) => s,
+            ShaderStorage::TransformedNearestPadImageAlpha(s) => s,
+            ShaderStorage::TransformedNearestRepeatImageAlpha(s) => s,
+            ShaderStorage::TransformedPadImageAlpha(s) => s,
+            ShaderStorage::TransformedRepeatImageAlpha(s) => s,
+            ShaderStorage::TransformedPadImage(s) => s,
+            ShaderStorage::TransformedRepeatImage(s) => s,
+            ShaderStorage::TransformedNearestPadImage(s) => s,
+            ShaderStorage::TransformedNearestRepeatImage(s) => s,
+            ShaderStorage::RadialGradient(s) => s,
+            ShaderStorage::TwoCircleRadialGradient(s) => s,
+            ShaderStorage::SweepGradient(s) => s,
+            ShaderStorage::LinearGradient(s) => s,
+            ShaderStorage::GradientF32(s) => s,
+            ShaderStorage::Mesh(s) => s,
+        }
+    }
+}
+
+pub enum ShaderBlitterStorage<'a, F: PixelFormat = Argb32> {
     None,
-    ShaderBlendMaskBlitter(ShaderBlendMaskBlitter<'a>),
-    ShaderClipBlendMaskBlitter(ShaderClipBlendMaskBlitter<'a>),
-    ShaderMaskBlitter(ShaderMaskBlitter<'a>),
-    ShaderClipMaskBlitter(ShaderClipMaskBlitter<'a>),
-    ShaderBlendBlitter(ShaderBlendBlitter<'a>),
+    ShaderBlendMaskBlitter(ShaderBlendMaskBlitter<'a, F>),
+    ShaderClipBlendMaskBlitter(ShaderClipBlendMaskBlitter<'a, F>),
+    ShaderMaskBlitter(ShaderMaskBlitter<'a, F>),
+    ShaderClipMaskBlitter(ShaderClipMaskBlitter<'a, F>),
+    ShaderBlendBlitter(ShaderBlendBlitter<'a, F>),
 }
 
 /*
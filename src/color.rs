@@ -0,0 +1,213 @@
+// Conversions between color spaces
+
+/// Decodes an sRGB encoded component to linear light
+pub(crate) fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a linear light component with the sRGB transfer function
+pub(crate) fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1. / 2.4) - 0.055
+    }
+}
+
+// The number of intervals in the encoding table. The curve is steep near zero so this
+// needs many more entries than the decoding table to get 8 bit values back exactly.
+const ENCODE_SIZE: usize = 4096;
+
+/// Tables for converting between sRGB and linear light. Values between the entries are
+/// interpolated so that the extra precision of high bit depth formats isn't lost.
+pub(crate) struct SrgbTables {
+    decode: Vec<f32>,
+    encode: Vec<f32>,
+}
+
+fn lookup(table: &[f32], c: f32) -> f32 {
+    let x = c * (table.len() - 1) as f32;
+    let i = x as usize;
+    let frac = x - i as f32;
+    if i + 1 < table.len() {
+        table[i] + (table[i + 1] - table[i]) * frac
+    } else {
+        table[table.len() - 1]
+    }
+}
+
+impl SrgbTables {
+    pub fn new() -> SrgbTables {
+        SrgbTables {
+            decode: (0..=255).map(|i| srgb_to_linear(i as f32 / 255.)).collect(),
+            encode: (0..=ENCODE_SIZE).map(|i| linear_to_srgb(i as f32 / ENCODE_SIZE as f32)).collect(),
+        }
+    }
+
+    pub fn decode(&self, c: f32) -> f32 {
+        if (0. ..=1.).contains(&c) {
+            lookup(&self.decode, c)
+        } else {
+            // HDR values are outside of the tables
+            c.signum() * srgb_to_linear(c.abs())
+        }
+    }
+
+    pub fn encode(&self, c: f32) -> f32 {
+        if (0. ..=1.).contains(&c) {
+            lookup(&self.encode, c)
+        } else {
+            c.signum() * linear_to_srgb(c.abs())
+        }
+    }
+
+    /// Decodes the color components of a premultiplied `[r, g, b, a]` color. The transfer
+    /// function applies to unpremultiplied components so they are unpremultiplied first
+    /// and premultiplied again afterwards.
+    pub fn decode_color(&self, color: &mut [f32; 4]) {
+        let a = color[3];
+        if a > 0. {
+            for c in &mut color[..3] {
+                *c = self.decode(*c / a) * a;
+            }
+        }
+    }
+
+    /// The inverse of `decode_color`
+    pub fn encode_color(&self, color: &mut [f32; 4]) {
+        let a = color[3];
+        if a > 0. {
+            for c in &mut color[..3] {
+                *c = self.encode(*c / a) * a;
+            }
+        }
+    }
+}
+
+// The matrices are from https://bottosson.github.io/posts/oklab/ with their published precision
+#[allow(clippy::excessive_precision)]
+fn linear_srgb_to_oklab(c: [f32; 3]) -> [f32; 3] {
+    let l = (0.4122214708 * c[0] + 0.5363325363 * c[1] + 0.0514459929 * c[2]).cbrt();
+    let m = (0.2119034982 * c[0] + 0.6806995451 * c[1] + 0.1073969566 * c[2]).cbrt();
+    let s = (0.0883024619 * c[0] + 0.2817188376 * c[1] + 0.6299787005 * c[2]).cbrt();
+    [
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    ]
+}
+
+#[allow(clippy::excessive_precision)]
+fn oklab_to_linear_srgb(c: [f32; 3]) -> [f32; 3] {
+    let l = (c[0] + 0.3963377774 * c[1] + 0.2158037573 * c[2]).powi(3);
+    let m = (c[0] - 0.1055613458 * c[1] - 0.0638541728 * c[2]).powi(3);
+    let s = (c[0] - 0.0894841775 * c[1] - 1.2914855480 * c[2]).powi(3);
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+}
+
+// Chroma below this is treated as gray which has no hue
+const ACHROMATIC: f32 = 1e-4;
+
+/// How hues are interpolated in polar color spaces like OKLCH. These are the hue
+/// interpolation methods of CSS Color 4.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum HueInterpolation {
+    /// Takes the shorter way around the hue circle
+    Shorter,
+    /// Takes the longer way around the hue circle
+    Longer,
+    /// Only increases the hue
+    Increasing,
+    /// Only decreases the hue
+    Decreasing,
+}
+
+/// The color space that gradient stops are interpolated in. `Srgb` interpolates the
+/// unpremultiplied sRGB components like plain gradients do. The other spaces interpolate
+/// premultiplied colors like CSS Color 4 does.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum GradientColorSpace {
+    #[default]
+    Srgb,
+    LinearSrgb,
+    Oklab,
+    Oklch(HueInterpolation),
+}
+
+impl GradientColorSpace {
+    /// Converts an unpremultiplied sRGB `[r, g, b, a]` color to this space. Alpha stays last.
+    pub(crate) fn convert_from_srgb(self, c: [f32; 4]) -> [f32; 4] {
+        let linear = || [srgb_to_linear(c[0]), srgb_to_linear(c[1]), srgb_to_linear(c[2])];
+        let [x, y, z] = match self {
+            GradientColorSpace::Srgb => return c,
+            GradientColorSpace::LinearSrgb => linear(),
+            GradientColorSpace::Oklab => linear_srgb_to_oklab(linear()),
+            GradientColorSpace::Oklch(_) => {
+                let [l, a, b] = linear_srgb_to_oklab(linear());
+                let mut hue = b.atan2(a).to_degrees();
+                if hue < 0. {
+                    hue += 360.;
+                }
+                [l, (a * a + b * b).sqrt(), hue]
+            }
+        };
+        [x, y, z, c[3]]
+    }
+
+    /// The inverse of `convert_from_srgb`. Colors outside of sRGB are clamped.
+    pub(crate) fn convert_to_srgb(self, c: [f32; 4]) -> [f32; 4] {
+        let linear = match self {
+            GradientColorSpace::Srgb => return c,
+            GradientColorSpace::LinearSrgb => [c[0], c[1], c[2]],
+            GradientColorSpace::Oklab => oklab_to_linear_srgb([c[0], c[1], c[2]]),
+            GradientColorSpace::Oklch(_) => {
+                let hue = c[2].to_radians();
+                oklab_to_linear_srgb([c[0], c[1] * hue.cos(), c[1] * hue.sin()])
+            }
+        };
+        let encode = |c: f32| linear_to_srgb(c.clamp(0., 1.));
+        [encode(linear[0]), encode(linear[1]), encode(linear[2]), c[3]]
+    }
+
+    /// Interpolates between two colors that are in this space
+    pub(crate) fn mix(self, c0: [f32; 4], c1: [f32; 4], t: f32) -> [f32; 4] {
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+        if self == GradientColorSpace::Srgb {
+            return [lerp(c0[0], c1[0]), lerp(c0[1], c1[1]), lerp(c0[2], c1[2]), lerp(c0[3], c1[3])];
+        }
+        let alpha = lerp(c0[3], c1[3]);
+        let unpremultiply = |c: f32| if alpha > 0. { c / alpha } else { 0. };
+        let mix = |i: usize| unpremultiply(lerp(c0[i] * c0[3], c1[i] * c1[3]));
+        match self {
+            GradientColorSpace::Oklch(method) => {
+                let (mut h0, mut h1) = (c0[2], c1[2]);
+                // gray has no hue so it takes the hue of the other color
+                if c0[1] < ACHROMATIC {
+                    h0 = h1;
+                } else if c1[1] < ACHROMATIC {
+                    h1 = h0;
+                }
+                let d = h1 - h0;
+                match method {
+                    HueInterpolation::Shorter if d > 180. => h0 += 360.,
+                    HueInterpolation::Shorter if d < -180. => h1 += 360.,
+                    HueInterpolation::Longer if d > 0. && d < 180. => h0 += 360.,
+                    HueInterpolation::Longer if d > -180. && d <= 0. => h1 += 360.,
+                    HueInterpolation::Increasing if d < 0. => h1 += 360.,
+                    HueInterpolation::Decreasing if d > 0. => h0 += 360.,
+                    _ => {}
+                }
+                [mix(0), mix(1), lerp(h0, h1) % 360., alpha]
+            }
+            _ => [mix(0), mix(1), mix(2), alpha],
+        }
+    }
+}
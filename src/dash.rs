@@ -2,7 +2,15 @@ use crate::path_builder::*;
 
 use crate::Point;
 
-use lyon_geom::LineSegment;
+use lyon_geom::{CubicBezierSegment, LineSegment, QuadraticBezierSegment};
+
+// How closely curves are flattened when measuring their length compared to the tolerance
+// they're drawn with. Errors in the lengths add up along the path so they need to be smaller.
+// Dash boundaries on curves are found by interpolating between the flattened points.
+const LENGTH_TOLERANCE_SCALE: f32 = 0.1;
+
+// The tolerance that the path is drawn with when it's dashed on its own
+const TOLERANCE: f32 = 0.1;
 
 #[derive(Clone, Copy)]
 struct DashState {
@@ -11,8 +19,202 @@ struct DashState {
     remaining_length: f32, // how much of the dash remains
 }
 
-pub fn dash_path(path: &Path, dash_array: &[f32], mut dash_offset: f32) -> Path {
-    let mut dashed = PathBuilder::new();
+// Maps lengths along a segment to the parameters of the segment
+struct Measure {
+    // pairs of parameters and the length of the segment up to them
+    lengths: Vec<(f32, f32)>,
+}
+
+impl Measure {
+    fn new(segment: &Segment, tolerance: f32) -> Measure {
+        let mut lengths = vec![(0., 0.)];
+        let mut length = 0.;
+        let mut add = |line: &LineSegment<f32>, t: std::ops::Range<f32>| {
+            length += line.length();
+            lengths.push((t.end, length));
+        };
+        match segment {
+            Segment::Line(line) => add(line, 0.0..1.),
+            Segment::Quadratic(quad) => quad.for_each_flattened_with_t(tolerance, &mut add),
+            Segment::Cubic(cubic) => cubic.for_each_flattened_with_t(tolerance, &mut add),
+        }
+        Measure { lengths }
+    }
+
+    fn length(&self) -> f32 {
+        self.lengths[self.lengths.len() - 1].1
+    }
+
+    fn parameter(&self, length: f32) -> f32 {
+        let i = self.lengths.iter().position(|&(_, l)| l >= length).unwrap_or(self.lengths.len() - 1).max(1);
+        let (t0, l0) = self.lengths[i - 1];
+        let (t1, l1) = self.lengths[i];
+        if l1 > l0 {
+            t0 + (t1 - t0) * ((length - l0) / (l1 - l0)).min(1.)
+        } else {
+            t1
+        }
+    }
+}
+
+// Returns the op that draws the part of `segment` between `t0` and `t1`
+fn segment_piece(segment: &Segment, t0: f32, t1: f32) -> PathOp {
+    match segment {
+        Segment::Line(line) => PathOp::LineTo(line.sample(t1)),
+        Segment::Quadratic(quad) => {
+            let piece = quad.split_range(t0..t1);
+            PathOp::QuadTo(piece.ctrl, piece.to)
+        }
+        Segment::Cubic(cubic) => {
+            let piece = cubic.split_range(t0..t1);
+            PathOp::CubicTo(piece.ctrl1, piece.ctrl2, piece.to)
+        }
+    }
+}
+
+struct Dasher<'a> {
+    dashed: PathBuilder,
+    dash_array: &'a [f32],
+    state: DashState,
+    tolerance: f32,
+    // To handle closed paths we need a bunch of extra state so that we properly
+    // join the first segment. Unfortunately, this makes the code sort of hairy.
+    // We need to store all of the ops in the initial dash so that we can
+    // join the end of the path with it.
+    is_first_segment: bool,
+    first_dash: bool,
+    initial_segment: Vec<PathOp>,
+}
+
+impl<'a> Dasher<'a> {
+    fn emit(&mut self, start: Point, op: PathOp) {
+        if self.is_first_segment {
+            if self.initial_segment.is_empty() {
+                self.initial_segment.push(PathOp::MoveTo(start));
+            }
+            self.initial_segment.push(op);
+        } else {
+            push_op(&mut self.dashed, op);
+        }
+    }
+
+    fn dash_segment(&mut self, segment: Segment) {
+        let (from, to) = match segment {
+            Segment::Line(line) => (line.from, line.to),
+            Segment::Quadratic(quad) => (quad.from, quad.to),
+            Segment::Cubic(cubic) => (cubic.from, cubic.to),
+        };
+        let measure = match segment {
+            Segment::Line(_) => None,
+            _ => Some(Measure::new(&segment, self.tolerance)),
+        };
+        let mut len = match &measure {
+            Some(measure) => measure.length(),
+            None => (to - from).length(),
+        };
+        let lv = (to - from).normalize();
+        let mut start = from;
+        let mut t = 0.;
+        let mut travelled = 0.;
+        while len > self.state.remaining_length {
+            travelled += self.state.remaining_length;
+            let (seg, next_t) = match &measure {
+                Some(measure) => {
+                    let next_t = measure.parameter(travelled);
+                    (segment_point(&segment, next_t), next_t)
+                }
+                None => (start + lv * self.state.remaining_length, 0.),
+            };
+            if self.state.on {
+                // zero length dashes are kept as degenerate lines so that they get capped
+                let op = if measure.is_none() || self.state.remaining_length == 0. {
+                    PathOp::LineTo(seg)
+                } else {
+                    segment_piece(&segment, t, next_t)
+                };
+                self.emit(start, op);
+            } else {
+                self.first_dash = false;
+                self.dashed.move_to(seg.x, seg.y);
+            }
+            self.is_first_segment = false;
+            self.state.on = !self.state.on;
+            self.state.index += 1;
+            len -= self.state.remaining_length;
+            self.state.remaining_length = self.dash_array[self.state.index % self.dash_array.len()];
+            start = seg;
+            t = next_t;
+        }
+        if self.state.on {
+            let op = match measure {
+                Some(_) if t < 1. => segment_piece(&segment, t, 1.),
+                _ => PathOp::LineTo(to),
+            };
+            self.emit(start, op);
+        } else {
+            self.first_dash = false;
+            self.dashed.move_to(to.x, to.y);
+        }
+        self.state.remaining_length -= len;
+    }
+
+    fn flush_initial_segment(&mut self) {
+        for op in std::mem::take(&mut self.initial_segment) {
+            push_op(&mut self.dashed, op);
+        }
+    }
+}
+
+fn push_op(dest: &mut PathBuilder, op: PathOp) {
+    match op {
+        PathOp::MoveTo(pt) => dest.move_to(pt.x, pt.y),
+        PathOp::LineTo(pt) => dest.line_to(pt.x, pt.y),
+        PathOp::QuadTo(ctrl, pt) => dest.quad_to(ctrl.x, ctrl.y, pt.x, pt.y),
+        PathOp::CubicTo(ctrl1, ctrl2, pt) => dest.cubic_to(ctrl1.x, ctrl1.y, ctrl2.x, ctrl2.y, pt.x, pt.y),
+        PathOp::Close => dest.close(),
+    }
+}
+
+fn segment_point(segment: &Segment, t: f32) -> Point {
+    match segment {
+        Segment::Line(line) => line.sample(t),
+        Segment::Quadratic(quad) => quad.sample(t),
+        Segment::Cubic(cubic) => cubic.sample(t),
+    }
+}
+
+/// Splits `path` into a subpath for each dash of `dash_array` starting `dash_offset`
+/// into the pattern. Curves are split at the dash boundaries instead of being flattened.
+/// Dashes of zero length become zero length lines which `stroke_to_path` draws as
+/// dots when the line cap is `Round` or `Square`.
+pub fn dash_path(path: &Path, dash_array: &[f32], dash_offset: f32) -> Path {
+    dash_path_with_tolerance(path, dash_array, dash_offset, None, TOLERANCE)
+}
+
+/// Like `dash_path` but the lengths in `dash_array` and `dash_offset` are relative to
+/// `path_length` instead of the actual length of `path`. This is SVG's `pathLength`.
+/// `StrokeStyle::path_length` does the same when stroking.
+pub fn dash_path_with_length(path: &Path, dash_array: &[f32], dash_offset: f32, path_length: f32) -> Path {
+    dash_path_with_tolerance(path, dash_array, dash_offset, Some(path_length), TOLERANCE)
+}
+
+/// Dashes `path`, scaling the dashes by `path_length` if it's set. `tolerance` is the one that
+/// the result will be drawn with so that the dashes end up in the right place when it's scaled up.
+pub(crate) fn dash_path_with_tolerance(path: &Path, dash_array: &[f32], dash_offset: f32, path_length: Option<f32>, tolerance: f32) -> Path {
+    let tolerance = tolerance * LENGTH_TOLERANCE_SCALE;
+    match path_length {
+        Some(path_length) if path_length.is_finite() && path_length > 0. => {
+            let length: f32 = path.segments().map(|segment| Measure::new(&segment, tolerance).length()).sum();
+            let scale = length / path_length;
+            let dash_array: Vec<f32> = dash_array.iter().map(|dash| dash * scale).collect();
+            dash_segments(path, &dash_array, dash_offset * scale, tolerance)
+        }
+        _ => dash_segments(path, dash_array, dash_offset, tolerance),
+    }
+}
+
+fn dash_segments(path: &Path, dash_array: &[f32], mut dash_offset: f32, tolerance: f32) -> Path {
+    let dashed = PathBuilder::new();
 
     let mut cur_pt = None;
     let mut start_point = None;
@@ -39,22 +241,15 @@ pub fn dash_path(path: &Path, dash_array: &[f32], mut dash_offset: f32) -> Path
         dash_offset += total_dash_length;
     }
 
-    // To handle closed paths we need a bunch of extra state so that we properly
-    // join the first segment. Unfortunately, this makes the code sort of hairy.
-    // We need to store all of the points in the initial segment so that we can
-    // join the end of the path with it.
-    let mut is_first_segment = true;
-    let mut first_dash = true;
-    let mut initial_segment : Vec<Point> = Vec::new();
-
     let mut state = DashState {
         on: true,
         remaining_length: dash_array[0],
         index: 0,
     };
 
-    // adjust our position in the dash array by the dash offset
-    while dash_offset > state.remaining_length {
+    // adjust our position in the dash array by the dash offset. Dashes that end exactly at
+    // the offset are skipped too unless they have zero length and would be drawn as a dot.
+    while dash_offset > state.remaining_length || (dash_offset == state.remaining_length && dash_offset > 0.) {
         dash_offset -= state.remaining_length;
         state.index += 1;
         state.remaining_length = dash_array[state.index % dash_array.len()];
@@ -64,143 +259,81 @@ pub fn dash_path(path: &Path, dash_array: &[f32], mut dash_offset: f32) -> Path
 
     // Save a copy of the initial state so that we can restore it for each subpath
     let initial = state;
+    let mut dasher = Dasher {
+        dashed,
+        dash_array,
+        state,
+        tolerance,
+        is_first_segment: true,
+        first_dash: true,
+        initial_segment: Vec::new(),
+    };
     for op in &path.ops {
         match *op {
             PathOp::MoveTo(pt) => {
                 cur_pt = Some(pt);
                 start_point = Some(pt);
-                dashed.move_to(pt.x, pt.y);
+                dasher.dashed.move_to(pt.x, pt.y);
 
                 // flush the previous initial segment
-                if initial_segment.len() > 0 {
-                    dashed.move_to(initial_segment[0].x, initial_segment[0].y);
-                    for i in 1..initial_segment.len() {
-                        dashed.line_to(initial_segment[i].x, initial_segment[i].y);
-                    }
-                }
-                is_first_segment = true;
-                initial_segment = Vec::new();
-                first_dash = true;
+                dasher.flush_initial_segment();
+                dasher.is_first_segment = true;
+                dasher.first_dash = true;
 
                 // reset the dash state
-                state = initial;
+                dasher.state = initial;
             }
             PathOp::LineTo(pt) => {
                 if let Some(cur_pt) = cur_pt {
-                    let mut start = cur_pt;
-                    let line = LineSegment {
-                        from: start,
-                        to: pt,
-                    };
-                    let mut len = line.length();
-                    let lv = line.to_vector().normalize();
-                    while len > state.remaining_length {
-                        let seg = start + lv * state.remaining_length;
-                        if state.on {
-                            if is_first_segment {
-                                initial_segment.push(start);
-                                initial_segment.push(seg);
-                            } else {
-                                dashed.line_to(seg.x, seg.y);
-                            }
-                        } else {
-                            first_dash = false;
-                            dashed.move_to(seg.x, seg.y);
-                        }
-                        is_first_segment = false;
-                        state.on = !state.on;
-                        state.index += 1;
-                        len -= state.remaining_length;
-                        state.remaining_length = dash_array[state.index % dash_array.len()];
-                        start = seg;
-                    }
-                    if state.on {
-                        if is_first_segment {
-                            initial_segment.push(start);
-                            initial_segment.push(pt);
-                        } else {
-                            dashed.line_to(pt.x, pt.y);
-                        }
-                    } else {
-                        first_dash = false;
-                        dashed.move_to(pt.x, pt.y);
-                    }
-                    state.remaining_length -= len;
+                    dasher.dash_segment(Segment::Line(LineSegment { from: cur_pt, to: pt }));
                 }
                 cur_pt = Some(pt);
             }
+            PathOp::QuadTo(ctrl, to) => {
+                // like the stroker, a curve without a current point starts at its first control point
+                let from = cur_pt.unwrap_or(ctrl);
+                dasher.dash_segment(Segment::Quadratic(QuadraticBezierSegment { from, ctrl, to }));
+                cur_pt = Some(to);
+            }
+            PathOp::CubicTo(ctrl1, ctrl2, to) => {
+                let from = cur_pt.unwrap_or(ctrl1);
+                dasher.dash_segment(Segment::Cubic(CubicBezierSegment { from, ctrl1, ctrl2, to }));
+                cur_pt = Some(to);
+            }
             PathOp::Close => {
                 if let (Some(current), Some(start_point)) = (cur_pt, start_point) {
-                    let mut start = current;
-                    let line = LineSegment {
-                        from: start,
-                        to: start_point,
-                    };
-                    let mut len = line.length();
-                    let lv = line.to_vector().normalize();
-
-                    while len > state.remaining_length {
-                        let seg = start + lv * state.remaining_length;
-                        if state.on {
-                            if is_first_segment {
-                                initial_segment.push(start);
-                                initial_segment.push(seg);
-                            } else {
-                                dashed.line_to(seg.x, seg.y);
-                            }
-                        } else {
-                            first_dash = false;
-                            dashed.move_to(seg.x, seg.y);
-                        }
-                        state.on = !state.on;
-                        state.index += 1;
-                        len -= state.remaining_length;
-                        state.remaining_length = dash_array[state.index % dash_array.len()];
-                        start = seg;
-                    }
+                    dasher.dash_segment(Segment::Line(LineSegment { from: current, to: start_point }));
 
-                    if state.on {
-                        if first_dash {
-                            // If we're still on the first dash we can just close
-                            dashed.close();
-                        } else {
-                            if initial_segment.len() > 0 {
-                                // If have an initial segment we'll need to connect with it
-                                for pt in initial_segment {
-                                    dashed.line_to(pt.x, pt.y);
-                                }
-                            } else {
-                                dashed.line_to(start_point.x, start_point.y);
+                    if dasher.state.on {
+                        if dasher.first_dash {
+                            // If we're still on the first dash the whole subpath is a single
+                            // dash and we can just close it
+                            for op in std::mem::take(&mut dasher.initial_segment).into_iter().skip(1) {
+                                push_op(&mut dasher.dashed, op);
                             }
-                        }
-                    } else {
-                        if initial_segment.len() > 0 {
-                            dashed.move_to(initial_segment[0].x, initial_segment[0].y);
-                            for i in 1..initial_segment.len() {
-                                dashed.line_to(initial_segment[i].x, initial_segment[i].y);
+                            dasher.dashed.close();
+                        } else if !dasher.initial_segment.is_empty() {
+                            // If have an initial segment we'll need to connect with it
+                            for op in std::mem::take(&mut dasher.initial_segment).into_iter().skip(1) {
+                                push_op(&mut dasher.dashed, op);
                             }
                         }
+                    } else {
+                        dasher.flush_initial_segment();
                     }
-                    initial_segment = Vec::new();
+                    dasher.initial_segment = Vec::new();
                     cur_pt = Some(start_point);
 
                     // reset the dash state
-                    state = initial;
+                    dasher.state = initial;
                 } else {
                     cur_pt = None;
                 }
             }
-            PathOp::QuadTo(..) => panic!("Only flat paths handled"),
-            PathOp::CubicTo(..) => panic!("Only flat paths handled"),
         }
     }
 
     // We still have an initial segment that we need to emit
-    if !initial_segment.is_empty() {
-        dashed.move_to(initial_segment[0].x, initial_segment[0].y);
-        for i in 1..initial_segment.len() {
-            dashed.line_to(initial_segment[i].x, initial_segment[i].y);
-        }
-    }
-    dashed.finish()
+    dasher.flush_initial_segment();
+    dasher.dashed.finish()
 }
@@ -1,9 +1,9 @@
 use crate::rasterizer::Rasterizer;
+use crate::hairline::hairline_mask;
 
 use crate::blitter::*;
 use sw_composite::*;
 
-use crate::dash::*;
 use crate::geom::*;
 use crate::path_builder::*;
 
@@ -24,8 +24,16 @@ use std::fs::*;
 #[cfg(feature = "png")]
 use std::io::BufWriter;
 
+use crate::color::{GradientColorSpace, SrgbTables};
+use crate::mesh::MeshGradient;
+use crate::format::{Argb32, PixelFormat, A8};
+use crate::gradient::resample;
+use crate::wide::*;
 use crate::stroke::*;
-use crate::{IntRect, IntPoint, Point, Transform, Vector};
+use crate::{IntRect, IntPoint, Point, Rect, Transform, Vector};
+
+use std::marker::PhantomData;
+use std::sync::Arc;
 
 use euclid::vec2;
 
@@ -75,6 +83,22 @@ impl From<Color> for SolidSource {
     }
 }
 
+/// A premultiplied color with `f32` components. `RgbaF32` targets keep components
+/// above 1 for HDR content. Other targets clamp them.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SolidSourceF32 {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl SolidSourceF32 {
+    pub fn from_unpremultiplied_rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        SolidSourceF32 { r: r * a, g: g * a, b: b * a, a }
+    }
+}
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum BlendMode {
     Dst,
@@ -201,6 +225,26 @@ fn build_blend_proc<T: Blender>(mode: BlendMode) -> T::Output {
     }
 }
 
+// Blits the spans of `rect` through `mask`, which covers `mask_rect`, or without a mask
+fn blit_rect(blitter: &mut dyn Blitter, mask: Option<&[u8]>, mask_rect: IntRect, rect: IntRect) {
+    match mask {
+        Some(mask) => {
+            for y in rect.min.y..rect.max.y {
+                let mask_row = (y - mask_rect.min.y) * mask_rect.size().width;
+                let mask_start = (mask_row + rect.min.x - mask_rect.min.x) as usize;
+                let mask_end = (mask_row + rect.max.x - mask_rect.min.x) as usize;
+                blitter.blit_span(y, rect.min.x, rect.max.x, &mask[mask_start..mask_end]);
+            }
+        }
+        None => {
+            for y in rect.min.y..rect.max.y {
+                let empty_mask = [];
+                blitter.blit_span(y, rect.min.x, rect.max.x, &empty_mask[..]);
+            }
+        }
+    };
+}
+
 #[derive(Copy, Clone)]
 pub enum ExtendMode {
     Pad,
@@ -226,11 +270,77 @@ pub enum FilterMode {
 #[derive(Clone)]
 pub enum Source<'a> {
     Solid(SolidSource),
+    SolidF32(SolidSourceF32),
     Image(Image<'a>, ExtendMode, FilterMode, Transform),
     RadialGradient(Gradient, Spread, Transform),
     TwoCircleRadialGradient(Gradient, Spread, Point, f32, Point, f32, Transform),
     LinearGradient(Gradient, Spread, Transform),
     SweepGradient(Gradient, Spread, f32, f32, Transform),
+    /// A gradient source drawn with options. Created by `Source::with_gradient_options`.
+    GradientWithOptions(Box<SourceWithOptions<'a>>),
+    /// A mesh of triangles and patches. The transform maps user space to the space of the mesh.
+    MeshGradient(&'a MeshGradient, Transform),
+}
+
+/// A source drawn with `GradientOptions`. See `Source::with_gradient_options`.
+#[derive(Clone)]
+pub struct SourceWithOptions<'a> {
+    pub(crate) source: Source<'a>,
+    pub(crate) options: GradientOptions,
+    // the stops resampled for the lookup table when the options interpolate in a color
+    // space other than sRGB so that this is only done once
+    pub(crate) resampled: Option<Gradient>,
+}
+
+impl<'a> SourceWithOptions<'a> {
+    /// The source that the options apply to
+    pub fn source(&self) -> &Source<'a> {
+        &self.source
+    }
+
+    /// The options that the source is drawn with
+    pub fn options(&self) -> GradientOptions {
+        self.options
+    }
+}
+
+/// Options for how the colors of gradient sources are computed
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct GradientOptions {
+    /// The color space that the stops are interpolated in
+    pub color_space: GradientColorSpace,
+    /// Adds an ordered dither when drawing to 8 bit targets so that large, subtle gradients
+    /// don't band. The gradient is evaluated at each pixel instead of being sampled from a
+    /// lookup table. Targets that blend in linear light dither when the blended pixels are
+    /// rounded to 8 bits, except inside layers. Targets with a high bit depth ignore this
+    /// because they don't round to 8 bits when drawing. Use `DrawTarget::to_argb32` to
+    /// dither them.
+    pub dither: bool,
+    /// How the colors of the gradient are found at each pixel
+    pub evaluation: GradientEvaluation,
+}
+
+/// How gradients find the color of each pixel
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum GradientEvaluation {
+    /// Samples a table with 256 entries. This is fast but stop positions snap to the
+    /// entries and gradients that cover large areas or have many stops show steps.
+    #[default]
+    LookupTable,
+    /// Evaluates the stops at each pixel with f32 precision. Stop positions, including
+    /// hard stops where two stops share a position, are exact.
+    PerPixel,
+}
+
+impl GradientOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    // Whether gradients with these options are sampled from a lookup table
+    pub(crate) fn uses_lookup_table(&self) -> bool {
+        !self.dither && self.evaluation == GradientEvaluation::LookupTable
+    }
 }
 
 impl From<SolidSource> for Source<'_> {
@@ -296,6 +406,38 @@ impl<'a> Source<'a> {
         let transform = Transform::translation(-center.x, -center.y);
         Source::SweepGradient(gradient, spread, start_angle, end_angle, transform)
     }
+
+    /// Creates a new mesh gradient source whose points are in user space
+    pub fn new_mesh_gradient(mesh: &'a MeshGradient) -> Source<'a> {
+        Source::MeshGradient(mesh, Transform::identity())
+    }
+
+    /// Returns a source that draws this gradient with `options`. Sources that aren't
+    /// gradients ignore the options.
+    pub fn with_gradient_options(self, options: GradientOptions) -> Source<'a> {
+        let source = match self {
+            Source::GradientWithOptions(with_options) => with_options.source,
+            source => source,
+        };
+        let resampled = match source.gradient() {
+            Some(gradient) if options.color_space != GradientColorSpace::Srgb && options.uses_lookup_table() => {
+                Some(resample(gradient, options.color_space))
+            }
+            _ => None,
+        };
+        Source::GradientWithOptions(Box::new(SourceWithOptions { source, options, resampled }))
+    }
+
+    /// Returns the stops of gradient sources
+    pub(crate) fn gradient(&self) -> Option<&Gradient> {
+        match self {
+            Source::RadialGradient(gradient, ..) |
+            Source::TwoCircleRadialGradient(gradient, ..) |
+            Source::LinearGradient(gradient, ..) |
+            Source::SweepGradient(gradient, ..) => Some(gradient),
+            _ => None,
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Copy, Debug)]
@@ -304,6 +446,17 @@ pub enum AntialiasMode {
     Gray,
 }
 
+/// How `DrawTarget::fill_coverage` combines the coverage of a path with an alpha only target
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CoverageOp {
+    /// Keeps the larger of the two coverages. This is the union of the shapes.
+    Max,
+    /// Keeps the smaller of the two coverages. This is the intersection of the shapes.
+    Min,
+    /// Multiplies the coverages
+    Multiply,
+}
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub struct DrawOptions {
     pub blend_mode: BlendMode,
@@ -330,27 +483,44 @@ impl Default for DrawOptions {
 #[derive(Clone)]
 struct Clip {
     rect: IntRect,
-    mask: Option<Vec<u8>>,
+    // masks cover the whole target so clips that only change the rect share them
+    mask: Option<Arc<Vec<u8>>>,
 }
 
 #[derive(Clone)]
 struct Layer {
     buf: Vec<u32>,
+    // used instead of `buf` for formats with a high bit depth
+    wide_buf: Vec<[f32; 4]>,
     opacity: f32,
     rect: IntRect,
     blend: BlendMode,
 }
 
-fn scaled_tolerance(x: f32, trans: &Transform) -> f32 {
-    // The absolute value of the determinant is the area parallelogram
-    // Take the sqrt of the area to losily convert to one dimension
-    x / trans.determinant().abs().sqrt()
+pub(crate) fn scaled_tolerance(x: f32, trans: &Transform) -> f32 {
+    // Distances in user space get stretched by at most the largest singular value
+    // of the transform so dividing by it keeps the error below `x` in device space
+    // even when the scale between axes is drastically different.
+    let scale = max_scale(trans);
+    if scale > 0. && scale.is_finite() {
+        x / scale
+    } else {
+        x
+    }
+}
+
+/// Returns the largest factor that `trans` can scale a distance by.
+fn max_scale(trans: &Transform) -> f32 {
+    let sum = trans.m11 * trans.m11 + trans.m12 * trans.m12 + trans.m21 * trans.m21 + trans.m22 * trans.m22;
+    let det = trans.determinant();
+    let discriminant = (sum * sum - 4. * det * det).max(0.);
+    ((sum + discriminant.sqrt()) / 2.).sqrt()
 }
 
 
 
-/// The main type used for drawing
-pub struct DrawTarget<Backing = Vec<u32>> {
+/// The main type used for drawing. The pixels are stored in `Backing` using `Format`.
+pub struct DrawTarget<Backing = Vec<u32>, Format = Argb32> {
     width: i32,
     height: i32,
     rasterizer: Rasterizer,
@@ -360,6 +530,9 @@ pub struct DrawTarget<Backing = Vec<u32>> {
     clip_stack: Vec<Clip>,
     layer_stack: Vec<Layer>,
     transform: Transform,
+    // set when blending in linear light
+    srgb: Option<Arc<SrgbTables>>,
+    format: PhantomData<Format>,
 }
 
 impl DrawTarget {
@@ -374,6 +547,8 @@ impl DrawTarget {
             clip_stack: Vec::new(),
             layer_stack: Vec::new(),
             transform: Transform::identity(),
+            srgb: None,
+            format: PhantomData,
         }
     }
 
@@ -389,7 +564,9 @@ impl DrawTarget {
             buf: vec,
             clip_stack: Vec::new(),
             layer_stack: Vec::new(),
-            transform: Transform::identity()
+            transform: Transform::identity(),
+            srgb: None,
+            format: PhantomData,
         }
     }
 
@@ -399,7 +576,15 @@ impl DrawTarget {
     }
 }
 
-impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
+impl<Format: PixelFormat> DrawTarget<Vec<Format::Pixel>, Format> {
+    /// Creates a DrawTarget that stores its pixels using `Format`.
+    /// e.g. `DrawTarget::<_, Rgba8>::with_format(width, height)`
+    pub fn with_format(width: i32, height: i32) -> Self {
+        DrawTarget::from_backing(width, height, vec![Format::Pixel::default(); (width * height) as usize])
+    }
+}
+
+impl<Backing : AsRef<[Format::Pixel]> + AsMut<[Format::Pixel]>, Format: PixelFormat> DrawTarget<Backing, Format> {
     /// Use an existing backing storage for the bitmap
     ///
     /// The backing store must be the correct size (width*height elements).
@@ -414,7 +599,9 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
             buf,
             clip_stack: Vec::new(),
             layer_stack: Vec::new(),
-            transform: Transform::identity()
+            transform: Transform::identity(),
+            srgb: None,
+            format: PhantomData,
         }
     }
 
@@ -426,6 +613,34 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         self.height
     }
 
+    /// Sets whether drawing blends in linear light. The pixels are still stored sRGB encoded
+    /// but they are decoded before anything is blended with them and encoded again
+    /// afterwards. This avoids the dark fringes on antialiased edges and the muddy
+    /// translucent colors that blending the encoded values gives. Layers hold decoded
+    /// values so the mode can only be changed when there are no layers. Returns false and
+    /// leaves the mode as it was when there are layers.
+    pub fn set_linear_blending(&mut self, linear: bool) -> bool {
+        if !self.layer_stack.is_empty() {
+            return false;
+        }
+        self.srgb = if linear {
+            Some(self.srgb.take().unwrap_or_else(|| Arc::new(SrgbTables::new())))
+        } else {
+            None
+        };
+        true
+    }
+
+    /// Returns whether drawing blends in linear light
+    pub fn linear_blending(&self) -> bool {
+        self.srgb.is_some()
+    }
+
+    // Whether drawing is done with f32 components
+    fn draws_wide(&self) -> bool {
+        Format::HIGH_BIT_DEPTH || self.srgb.is_some()
+    }
+
     /// sets a transform that will be applied to all drawing operations
     pub fn set_transform(&mut self, transform: &Transform) {
         self.transform = *transform;
@@ -555,10 +770,10 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         let clip = match self.clip_stack.last() {
             Some(Clip {
                      rect: current_clip,
-                     mask: _,
+                     mask,
                  }) => Clip {
                 rect: current_clip.intersection_unchecked(&rect),
-                mask: None,
+                mask: mask.clone(),
             },
             _ => Clip {
                 rect: rect,
@@ -568,22 +783,120 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         self.clip_stack.push(clip);
     }
 
+    /// Like `push_clip_rect` but `rect` is transformed by the current transform. When the
+    /// result is pixel aligned this is as cheap as `push_clip_rect`, otherwise the edges
+    /// of the rect are antialiased.
+    pub fn push_clip_rect_f32(&mut self, rect: Rect) {
+        let corners = [
+            rect.min,
+            Point::new(rect.max.x, rect.min.y),
+            rect.max,
+            Point::new(rect.min.x, rect.max.y),
+        ].map(|pt| self.transform.transform_point(pt));
+        let bounds = Rect::from_points(corners.iter());
+        let int_bounds = bounds.round_out().to_i32();
+        let axis_aligned = (corners[0].y == corners[1].y && corners[0].x == corners[3].x)
+            || (corners[0].x == corners[1].x && corners[0].y == corners[3].y);
+        if axis_aligned && int_bounds.to_f32() == bounds {
+            self.push_clip_rect(int_bounds);
+            return;
+        }
+
+        // Clip masks are indexed in target coordinates so the mask still covers the whole
+        // target, but only the bounds of the rect are rasterized and combined with the
+        // current mask. Nothing outside of them can be drawn so they restrict the clip rect too.
+        let mut mask = vec![0; (self.width * self.height) as usize];
+        let mut pb = PathBuilder::new();
+        pb.rect(rect.min.x, rect.min.y, rect.width(), rect.height());
+        self.apply_path(&pb.finish());
+        let bounds = self.rasterizer.get_bounds();
+        if bounds.size().width > 0 && bounds.size().height > 0 {
+            let width = bounds.size().width;
+            let mut blitter = MaskSuperBlitter::new(bounds.min.x, bounds.min.y, width, bounds.size().height);
+            self.rasterizer.rasterize(&mut blitter, Winding::NonZero);
+            let last_mask = self.clip_stack.last().and_then(|clip| clip.mask.as_ref());
+            for y in bounds.min.y..bounds.max.y {
+                let src = &blitter.buf[((y - bounds.min.y) * width) as usize..][..width as usize];
+                let start = (y * self.width + bounds.min.x) as usize;
+                for (i, &coverage) in (start..start + width as usize).zip(src) {
+                    mask[i] = match last_mask {
+                        Some(last_mask) => muldiv255(coverage as u32, last_mask[i] as u32) as u8,
+                        None => coverage,
+                    };
+                }
+            }
+        }
+        self.rasterizer.reset();
+        let rect = self.clip_bounds().intersection_unchecked(&int_bounds);
+        self.clip_stack.push(Clip { rect, mask: Some(Arc::new(mask)) });
+    }
+
     pub fn pop_clip(&mut self) {
         self.clip_stack.pop();
     }
 
     pub fn push_clip(&mut self, path: &Path) {
-        self.apply_path(path);
+        self.push_clip_with_antialias(path, AntialiasMode::Gray);
+    }
 
+    /// Like `push_clip` but `antialias` controls whether the edges of the clip are antialiased.
+    /// Aliased clips avoid seams where adjacent clipped regions meet.
+    pub fn push_clip_with_antialias(&mut self, path: &Path, antialias: AntialiasMode) {
         // XXX: restrict to clipped area
-        let mut blitter = MaskSuperBlitter::new(0, 0, self.width, self.height);
-        self.rasterizer.rasterize(&mut blitter, path.winding);
+        let mask = self.rasterize_to_mask(path, antialias);
+        self.push_clip_mask(mask);
+    }
+
+    // Returns the coverage of `path` for the whole draw target
+    fn rasterize_to_mask(&mut self, path: &Path, antialias: AntialiasMode) -> Vec<u8> {
+        self.apply_path(path);
+        let mask = match antialias {
+            AntialiasMode::None => {
+                let mut blitter = MaskBlitter::new(0, 0, self.width, self.height);
+                self.rasterizer.rasterize(&mut blitter, path.winding);
+                blitter.buf
+            }
+            AntialiasMode::Gray => {
+                let mut blitter = MaskSuperBlitter::new(0, 0, self.width, self.height);
+                self.rasterizer.rasterize(&mut blitter, path.winding);
+                blitter.buf
+            }
+        };
+        self.rasterizer.reset();
+        mask
+    }
 
+    /// Removes the area covered by `path` from the clip
+    pub fn push_clip_out(&mut self, path: &Path) {
+        let winding = match path.winding {
+            Winding::EvenOdd => Winding::InverseEvenOdd,
+            Winding::NonZero => Winding::InverseNonZero,
+            Winding::InverseEvenOdd => Winding::EvenOdd,
+            Winding::InverseNonZero => Winding::NonZero,
+        };
+        self.push_clip(&Path { ops: path.ops.clone(), winding });
+    }
+
+    /// Removes `rect` from the clip
+    pub fn push_clip_out_rect(&mut self, rect: IntRect) {
+        let mut mask = vec![0xff; (self.width * self.height) as usize];
+        let rect = rect.intersection_unchecked(&intrect(0, 0, self.width, self.height));
+        for y in rect.min.y..rect.max.y {
+            let row = (y * self.width) as usize;
+            for x in rect.min.x..rect.max.x {
+                mask[row + x as usize] = 0;
+            }
+        }
+        self.push_clip_mask(mask);
+    }
+
+    // Pushes a clip with a coverage mask for the whole draw target
+    fn push_clip_mask(&mut self, mut mask: Vec<u8>) {
         if let Some(last) = self.clip_stack.last() {
             // combine with previous mask
             if let Some(last_mask) = &last.mask {
                 for i in 0..((self.width * self.height) as usize) {
-                    blitter.buf[i] = muldiv255(blitter.buf[i] as u32, last_mask[i] as u32) as u8
+                    mask[i] = muldiv255(mask[i] as u32, last_mask[i] as u32) as u8
                 }
             }
         }
@@ -592,9 +905,8 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         //XXX: handle interleaving of clip rect/masks better
         self.clip_stack.push(Clip {
             rect: current_bounds,
-            mask: Some(blitter.buf),
+            mask: Some(Arc::new(mask)),
         });
-        self.rasterizer.reset();
     }
 
     fn clip_bounds(&self) -> IntRect {
@@ -614,9 +926,16 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
     /// group opacity or blend effects.
     pub fn push_layer_with_blend(&mut self, opacity: f32, blend: BlendMode) {
         let rect = self.clip_bounds();
+        let len = (rect.size().width * rect.size().height) as usize;
+        let (buf, wide_buf) = if self.draws_wide() {
+            (Vec::new(), vec![[0.; 4]; len])
+        } else {
+            (vec![0; len], Vec::new())
+        };
         self.layer_stack.push(Layer {
             rect,
-            buf: vec![0; (rect.size().width * rect.size().height) as usize],
+            buf,
+            wide_buf,
             opacity,
             blend
         });
@@ -626,6 +945,15 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
     /// the pushed opacity applied.
     pub fn pop_layer(&mut self) {
         let layer = self.layer_stack.pop().unwrap();
+        if self.draws_wide() {
+            let shader = LayerShader { buf: &layer.wide_buf, rect: layer.rect, opacity: layer.opacity };
+            let bounds = intrect(0, 0, self.width, self.height);
+            let rect = self.composite_bounds(bounds, layer.rect);
+            if !rect.is_empty() {
+                self.composite_wide(&shader, None, bounds, rect, layer.blend, false);
+            }
+            return;
+        }
         let opacity = (layer.opacity * 255. + 0.5) as u8;
         // Allocating an entire mask just for the opacity is needlessly bad.
         // We should be able to fix it once the blitters work better.
@@ -671,22 +999,81 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
     pub fn stroke(&mut self, path: &Path, src: &Source, style: &StrokeStyle, options: &DrawOptions) {
         let tolerance = 0.1;
 
-        // Since we're flattening in userspace, we need to compensate for the transform otherwise
-        // we'll flatten too much or not enough depending on the scale. We approximate doing this
-        // correctly by scaling the tolerance value using the same mechanism as Fitz. This
-        // approximation will fail if the scale between axes is drastically different. An
-        // alternative would be to use transform specific flattening but I haven't seen that done
-        // anywhere.
+        // Since we're stroking in userspace, we need to compensate for the transform otherwise
+        // we'll flatten too much or not enough depending on the scale.
         let tolerance = scaled_tolerance(tolerance, &self.transform);
-        let mut path = path.flatten(tolerance);
+        let stroked = dash_and_stroke(path, style, tolerance);
+        self.fill(&stroked, src, options);
+    }
 
-        if !style.dash_array.is_empty() {
-            path = dash_path(&path, &style.dash_array, style.dash_offset);
-        }
-        let stroked = stroke_to_path(&path, style);
+    /// Strokes `path` like `stroke` but with the dash array and offset of `style` relative
+    /// to `path_length` instead of the actual length of the path. This is SVG's `pathLength`.
+    pub fn stroke_with_path_length(&mut self, path: &Path, src: &Source, style: &StrokeStyle, path_length: f32, options: &DrawOptions) {
+        let tolerance = scaled_tolerance(0.1, &self.transform);
+        let stroked = dash_and_stroke_with_length(path, style, Some(path_length), tolerance);
+        self.fill(&stroked, src, options);
+    }
+
+    /// Strokes `path` with a width that varies along it and fills the result with `src`.
+    /// `width` is called with the distance along the path. See `stroke_to_path_with_width`.
+    pub fn stroke_with_width(&mut self, path: &Path, src: &Source, style: &StrokeStyle, width: impl Fn(f32) -> f32, options: &DrawOptions) {
+        let tolerance = scaled_tolerance(0.1, &self.transform);
+        let stroked = stroke_to_path_with_width(path, style, tolerance, width);
+        self.fill(&stroked, src, options);
+    }
+
+    /// Strokes `path` with a width for each of its points and fills the result with `src`.
+    /// See `stroke_to_path_with_widths`.
+    pub fn stroke_with_widths(&mut self, path: &Path, src: &Source, style: &StrokeStyle, widths: &[f32], options: &DrawOptions) {
+        let tolerance = scaled_tolerance(0.1, &self.transform);
+        let stroked = stroke_to_path_with_widths(path, style, tolerance, widths);
         self.fill(&stroked, src, options);
     }
 
+    /// Strokes `path` with `style` in device space and fills the result with `src`.
+    /// The path is still transformed but the width of the stroke and the dashes are not
+    /// affected by the transform. This is the equivalent of SVG's `non-scaling-stroke`.
+    pub fn stroke_non_scaling(&mut self, path: &Path, src: &Source, style: &StrokeStyle, options: &DrawOptions) {
+        let inverse = match self.transform.inverse() {
+            Some(inverse) => inverse,
+            // the transform is not invertible so we have nothing to draw
+            None => return,
+        };
+        let device_path = path.clone().transform(&self.transform);
+        let stroked = dash_and_stroke(&device_path, style, 0.1);
+        // map the stroke back to user space so that `src` keeps using the current transform
+        self.fill(&stroked.transform(&inverse), src, options);
+    }
+
+    /// Strokes `path` with an antialiased line that is one device pixel wide regardless
+    /// of the current transform and fills it with `src`. This is useful for things like
+    /// gridlines and wireframes that need to stay crisp at any zoom level. The coverage of
+    /// overlapping segments adds up so the points where a path turns can be brighter.
+    pub fn stroke_hairline(&mut self, path: &Path, src: &Source, options: &DrawOptions) {
+        let path = path.clone().transform(&self.transform).flatten(0.1);
+        let bounds = match path.control_bounds() {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        // pixels next to the end points of the lines can get coverage
+        let bounds = intrect(
+            (bounds.min.x.floor() as i32).saturating_sub(1),
+            (bounds.min.y.floor() as i32).saturating_sub(1),
+            (bounds.max.x.ceil() as i32).saturating_add(1),
+            (bounds.max.y.ceil() as i32).saturating_add(1),
+        );
+        let bounds = match bounds.intersection(&intrect(0, 0, self.width, self.height)) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+        let antialias = match options.antialias {
+            AntialiasMode::None => false,
+            AntialiasMode::Gray => true,
+        };
+        let mask = hairline_mask(&path, bounds, antialias);
+        self.composite(src, Some(&mask), bounds, bounds, options.blend_mode, options.alpha);
+    }
+
     /// Fills the rect `x`, `y,`, `width`, `height` with `src`. If the result is an
     /// integer aligned rectangle performance will be faster than filling a rectangular path.
     pub fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, src: &Source, options: &DrawOptions) {
@@ -715,7 +1102,12 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
     /// Fills `path` with `src`
     pub fn fill(&mut self, path: &Path, src: &Source, options: &DrawOptions) {
         self.apply_path(path);
-        let bounds = self.rasterizer.get_bounds();
+        let bounds = if path.winding.is_inverse() {
+            // inverse fills can paint anywhere
+            intrect(0, 0, self.width, self.height)
+        } else {
+            self.rasterizer.get_bounds()
+        };
         if bounds.size().width > 0 && bounds.size().height > 0 {
             match options.antialias {
                 AntialiasMode::None => {
@@ -752,8 +1144,18 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         let mut pb = PathBuilder::new();
         if self.clip_stack.is_empty() {
             let color = solid.to_u32();
-            for pixel in self.buf.as_mut() {
-                *pixel = color;
+            match Format::as_argb32(self.buf.as_mut()) {
+                Some(buf) => {
+                    for pixel in buf {
+                        *pixel = color;
+                    }
+                }
+                None => {
+                    let row = vec![color; self.width as usize];
+                    for pixels in self.buf.as_mut().chunks_mut(self.width as usize) {
+                        Format::store(&row[..pixels.len()], pixels);
+                    }
+                }
             }
         } else {
             let ctm = self.transform;
@@ -862,7 +1264,7 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
 }
 
 impl DrawTarget {
-    fn choose_blitter<'a, 'b, 'c>(mask: Option<&[u8]>, clip_stack: &'a Vec<Clip>, blitter_storage: &'b mut ShaderBlitterStorage<'a>, shader: &'a dyn Shader, blend: BlendMode, dest: &'a mut [u32], dest_bounds: IntRect, width: i32) -> &'b mut dyn Blitter {
+    fn choose_blitter<'a, 'b, F: PixelFormat>(mask: Option<&[u8]>, clip_stack: &'a Vec<Clip>, blitter_storage: &'b mut ShaderBlitterStorage<'a, F>, shader: &'a dyn Shader, blend: BlendMode, dest: &'a mut [F::Pixel], dest_bounds: IntRect, width: i32) -> &'b mut dyn Blitter {
         *blitter_storage = match (mask, clip_stack.last()) {
             (Some(_mask), Some(Clip {
                         rect: _,
@@ -878,6 +1280,7 @@ impl DrawTarget {
                         dest_stride: dest_bounds.size().width,
                         clip,
                         clip_stride: width,
+                        row: Vec::new(),
                     };
                     ShaderBlitterStorage::ShaderClipMaskBlitter(scb)
                 } else {
@@ -891,7 +1294,8 @@ impl DrawTarget {
                         dest_stride: dest_bounds.size().width,
                         clip,
                         clip_stride: width,
-                        blend_fn
+                        blend_fn,
+                        row: Vec::new(),
                     };
                     ShaderBlitterStorage::ShaderClipBlendMaskBlitter(scb_blend)
                 }
@@ -905,6 +1309,7 @@ impl DrawTarget {
                         tmp: vec![0; width as usize],
                         dest,
                         dest_stride: dest_bounds.size().width,
+                        row: Vec::new(),
                     };
                     ShaderBlitterStorage::ShaderMaskBlitter(sb)
                 } else {
@@ -917,6 +1322,7 @@ impl DrawTarget {
                         dest,
                         dest_stride: dest_bounds.size().width,
                         blend_fn,
+                        row: Vec::new(),
                     };
                     ShaderBlitterStorage::ShaderBlendMaskBlitter(sb_blend)
                 }
@@ -931,6 +1337,7 @@ impl DrawTarget {
                     dest,
                     dest_stride: dest_bounds.size().width,
                     blend_fn,
+                    row: Vec::new(),
                 };
                 ShaderBlitterStorage::ShaderBlendBlitter(sb_blend)
             }
@@ -947,10 +1354,10 @@ impl DrawTarget {
     }
 }
 
-impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
+impl<Backing : AsRef<[Format::Pixel]> + AsMut<[Format::Pixel]>, Format: PixelFormat> DrawTarget<Backing, Format> {
     /// `mask_rect` is in DrawTarget space. i.e size is the size of the mask and origin is the position.
     /// you can not render a part of the mask
-    fn composite(&mut self, src: &Source, mask: Option<&[u8]>, mask_rect: IntRect, mut rect: IntRect, blend: BlendMode, alpha: f32) {
+    fn composite(&mut self, src: &Source, mask: Option<&[u8]>, mask_rect: IntRect, rect: IntRect, blend: BlendMode, alpha: f32) {
         let ti = self.transform.inverse();
         let ti = if let Some(ti) = ti {
             ti
@@ -959,45 +1366,173 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
             return;
         };
 
-        let clip_bounds = self.clip_bounds();
-
-        let (dest, dest_bounds) = match self.layer_stack.last_mut() {
-            Some(layer) => (&mut layer.buf[..], layer.rect),
-            None => (self.buf.as_mut(), intrect(0, 0, self.width, self.height))
-        };
-
-        rect = rect
-            .intersection_unchecked(&clip_bounds)
-            .intersection_unchecked(&dest_bounds)
-            .intersection_unchecked(&mask_rect);
+        let rect = self.composite_bounds(mask_rect, rect);
         if rect.is_empty() {
             return;
         }
 
+        if self.draws_wide() {
+            let mut shader_storage = WideShaderStorage::None;
+            let shader = choose_wide_shader(&ti, src, alpha, rect, &mut shader_storage);
+            let dither = matches!(src, Source::GradientWithOptions(with_options) if with_options.options.dither);
+            match self.srgb.clone() {
+                Some(tables) => self.composite_wide(&DecodeShader { shader, tables: &tables }, mask, mask_rect, rect, blend, dither),
+                None => self.composite_wide(shader, mask, mask_rect, rect, blend, dither),
+            }
+            return;
+        }
+
         let mut shader_storage = ShaderStorage::None;
-        let shader = choose_shader(&ti, src, alpha, &mut shader_storage);
-
-        let mut blitter_storage = ShaderBlitterStorage::None;
-        let blitter = DrawTarget::choose_blitter(mask, &self.clip_stack, &mut blitter_storage, shader, blend, dest, dest_bounds, self.width);
-
-        match mask {
-            Some(mask) => {
-                for y in rect.min.y..rect.max.y {
-                    let mask_row = (y - mask_rect.min.y) * mask_rect.size().width;
-                    let mask_start = (mask_row + rect.min.x - mask_rect.min.x) as usize;
-                    let mask_end = (mask_row + rect.max.x - mask_rect.min.x) as usize;
-                    blitter.blit_span(y, rect.min.x, rect.max.x, &mask[mask_start..mask_end]);
-                }
+        let shader = choose_shader(&ti, src, alpha, rect, &mut shader_storage);
+
+        let width = self.width;
+        match self.layer_stack.last_mut() {
+            Some(layer) => {
+                let mut blitter_storage = ShaderBlitterStorage::<Argb32>::None;
+                let blitter = DrawTarget::choose_blitter(mask, &self.clip_stack, &mut blitter_storage, shader, blend, &mut layer.buf[..], layer.rect, width);
+                blit_rect(blitter, mask, mask_rect, rect);
             }
             None => {
-                for y in rect.min.y..rect.max.y {
-                    let empty_mask = [];
-                    blitter.blit_span(y, rect.min.x, rect.max.x, &empty_mask[..]);
+                let mut blitter_storage = ShaderBlitterStorage::<Format>::None;
+                let blitter = DrawTarget::choose_blitter(mask, &self.clip_stack, &mut blitter_storage, shader, blend, self.buf.as_mut(), intrect(0, 0, width, self.height), width);
+                blit_rect(blitter, mask, mask_rect, rect);
+            }
+        }
+    }
+
+    // Returns the part of `rect` that drawing through a mask at `mask_rect` can change
+    fn composite_bounds(&self, mask_rect: IntRect, rect: IntRect) -> IntRect {
+        let layer_bounds = self.layer_stack.last().map(|layer| layer.rect);
+        rect
+            .intersection_unchecked(&self.clip_bounds())
+            .intersection_unchecked(&layer_bounds.unwrap_or(intrect(0, 0, self.width, self.height)))
+            .intersection_unchecked(&mask_rect)
+    }
+
+    // Like `composite` but with f32 components for formats with a high bit depth or when
+    // blending in linear light. `rect` must already be limited to `composite_bounds`.
+    // Layers hold linear values already so only the target's pixels are decoded.
+    // `dither` rounds the result with an ordered dither when it is stored to an 8 bit format
+    fn composite_wide(&mut self, shader: &dyn WideShader, mask: Option<&[u8]>, mask_rect: IntRect, rect: IntRect, blend: BlendMode, dither: bool) {
+        let blend_fn = build_blend_proc_f32(blend);
+        let clip = self.clip_stack.last().and_then(|clip| clip.mask.as_ref());
+        let width = rect.size().width as usize;
+        let mut src = vec![[0.; 4]; width];
+        let mut converted = vec![[0.; 4]; width];
+        let dither = dither && !Format::HIGH_BIT_DEPTH;
+        let mut dithered = if dither { vec![0; width] } else { Vec::new() };
+        for y in rect.min.y..rect.max.y {
+            shader.shade_span_wide(rect.min.x, y, &mut src);
+            let start = (y * self.width + rect.min.x) as usize;
+            let dest = match self.layer_stack.last_mut() {
+                Some(layer) => {
+                    let layer_start = ((y - layer.rect.min.y) * layer.rect.size().width + rect.min.x - layer.rect.min.x) as usize;
+                    &mut layer.wide_buf[layer_start..layer_start + width]
                 }
+                None => {
+                    Format::load_f32(&self.buf.as_ref()[start..start + width], &mut converted);
+                    if let Some(srgb) = &self.srgb {
+                        converted.iter_mut().for_each(|pixel| srgb.decode_color(pixel));
+                    }
+                    &mut converted[..]
+                }
+            };
+            let mask_start = ((y - mask_rect.min.y) * mask_rect.size().width + rect.min.x - mask_rect.min.x) as usize;
+            for (i, (dest, src)) in dest.iter_mut().zip(&src).enumerate() {
+                let mut coverage = 1.;
+                if let Some(mask) = mask {
+                    coverage *= mask[mask_start + i] as f32 / 255.;
+                }
+                if let Some(clip) = clip {
+                    coverage *= clip[start + i] as f32 / 255.;
+                }
+                blend_with_coverage(*src, dest, coverage, blend_fn);
             }
-        };
+            if self.layer_stack.is_empty() {
+                if let Some(srgb) = &self.srgb {
+                    converted.iter_mut().for_each(|pixel| srgb.encode_color(pixel));
+                }
+                if dither {
+                    row_to_argb32(&converted, &mut dithered, rect.min.x, y, true);
+                    Format::store(&dithered, &mut self.buf.as_mut()[start..start + width]);
+                } else {
+                    Format::store_f32(&converted, &mut self.buf.as_mut()[start..start + width]);
+                }
+            }
+        }
+    }
+
+    /// Converts the pixels to a new `Argb32` DrawTarget. When `dither` is true an ordered
+    /// dither is applied while rounding to 8 bits per channel so that smooth gradients
+    /// drawn to a high bit depth target don't band.
+    pub fn to_argb32(&self, dither: bool) -> DrawTarget {
+        let mut dt = DrawTarget::new(self.width, self.height);
+        let width = self.width as usize;
+        if width == 0 {
+            return dt;
+        }
+        let mut row = vec![[0.; 4]; width];
+        for (y, (src, dst)) in self.buf.as_ref().chunks(width).zip(dt.buf.chunks_mut(width)).enumerate() {
+            Format::load_f32(src, &mut row);
+            row_to_argb32(&row, dst, 0, y as i32, dither);
+        }
+        dt
+    }
+
+    /// Returns a reference to the underlying pixel data
+    pub fn get_data(&self) -> &[Format::Pixel] {
+        self.buf.as_ref()
+    }
+
+    /// Returns a mut reference to the underlying pixel data. For `Argb32` targets the
+    /// pixels have a representation like: (A << 24) | (R << 16) | (G << 8) | B
+    pub fn get_data_mut(&mut self) -> &mut [Format::Pixel] {
+        self.buf.as_mut()
+    }
+
+    /// Take ownership of the buffer backing the DrawTarget
+    pub fn into_inner(self) -> Backing {
+        self.buf
+    }
+
+    /// Saves the current pixel to a png file at `path`
+    #[cfg(feature = "png")]
+    pub fn write_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), png::EncodingError> {
+        let file = File::create(path)?;
+
+        let w = &mut BufWriter::new(file);
+
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        let mut buf = vec![0; self.buf.as_ref().len()];
+        Format::load(self.buf.as_ref(), &mut buf);
+        let mut output = Vec::with_capacity(buf.len() * 4);
+
+        for pixel in buf {
+            let a = (pixel >> 24) & 0xffu32;
+            let mut r = (pixel >> 16) & 0xffu32;
+            let mut g = (pixel >> 8) & 0xffu32;
+            let mut b = pixel & 0xffu32;
+
+            if a > 0u32 {
+                r = r * 255u32 / a;
+                g = g * 255u32 / a;
+                b = b * 255u32 / a;
+            }
+
+            output.push(r as u8);
+            output.push(g as u8);
+            output.push(b as u8);
+            output.push(a as u8);
+        }
+
+        writer.write_image_data(&output)
     }
+}
 
+impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
     /// Draws `src_rect` of `src` at `dst`. The current transform and clip are ignored
     pub fn composite_surface<F: Fn(&[u32], &mut [u32]), SrcBacking: AsRef<[u32]>>(&mut self, src: &DrawTarget<SrcBacking>, src_rect: IntRect, dst: IntPoint, f: F) {
         let dst_rect = intrect(0, 0, self.width, self.height);
@@ -1053,17 +1588,6 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         });
     }
 
-    /// Returns a reference to the underlying pixel data
-    pub fn get_data(&self) -> &[u32] {
-        self.buf.as_ref()
-    }
-
-    /// Returns a mut reference to the underlying pixel data as ARGB with a representation
-    /// like: (A << 24) | (R << 16) | (G << 8) | B
-    pub fn get_data_mut(&mut self) -> &mut [u32] {
-        self.buf.as_mut()
-    }
-
     /// Returns a reference to the underlying pixel data as individual bytes with the order BGRA
     /// on little endian.
     pub fn get_data_u8(&self) -> &[u8] {
@@ -1085,44 +1609,50 @@ impl<Backing : AsRef<[u32]> + AsMut<[u32]>> DrawTarget<Backing> {
         // do because requirements of a [u32] slice are stricter.
         unsafe { std::slice::from_raw_parts_mut(p as *mut u8, len * std::mem::size_of::<u32>()) }
     }
+}
 
-    /// Take ownership of the buffer backing the DrawTarget
-    pub fn into_inner(self) -> Backing {
-        self.buf
+impl<Backing : AsRef<[u8]> + AsMut<[u8]>> DrawTarget<Backing, A8> {
+    /// Combines the coverage of `path` with the coverage that's already in the target using `op`.
+    /// Unlike `fill` this also changes the parts of the target outside of the path unless `op`
+    /// is `CoverageOp::Max`. The blend mode of `options` and any layers are ignored but the clip is not.
+    pub fn fill_coverage(&mut self, path: &Path, op: CoverageOp, options: &DrawOptions) {
+        let alpha = (options.alpha.clamp(0., 1.) * 255. + 0.5) as u32;
+        let mask = self.rasterize_to_mask(path, options.antialias);
+        let rect = self.clip_bounds();
+        let clip = self.clip_stack.last().and_then(|clip| clip.mask.as_ref());
+        let buf = self.buf.as_mut();
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                let i = (y * self.width + x) as usize;
+                let coverage = muldiv255(mask[i] as u32, alpha);
+                let dst = buf[i] as u32;
+                let result = match op {
+                    CoverageOp::Max => dst.max(coverage),
+                    CoverageOp::Min => dst.min(coverage),
+                    CoverageOp::Multiply => muldiv255(dst, coverage),
+                };
+                buf[i] = match clip {
+                    Some(clip) => (dst + muldiv255(result, clip[i] as u32) - muldiv255(dst, clip[i] as u32)).min(255) as u8,
+                    None => result as u8,
+                };
+            }
+        }
     }
+}
 
-    /// Saves the current pixel to a png file at `path`
-    #[cfg(feature = "png")]
-    pub fn write_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), png::EncodingError> {
-        let file = File::create(path)?;
-
-        let w = &mut BufWriter::new(file);
-
-        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
-        encoder.set_color(png::ColorType::Rgba);
-        encoder.set_depth(png::BitDepth::Eight);
-        let mut writer = encoder.write_header()?;
-        let buf = self.buf.as_ref();
-        let mut output = Vec::with_capacity(buf.len() * 4);
-
-        for pixel in buf {
-            let a = (pixel >> 24) & 0xffu32;
-            let mut r = (pixel >> 16) & 0xffu32;
-            let mut g = (pixel >> 8) & 0xffu32;
-            let mut b = (pixel >> 0) & 0xffu32;
-
-            if a > 0u32 {
-                r = r * 255u32 / a;
-                g = g * 255u32 / a;
-                b = b * 255u32 / a;
-            }
+impl DrawTarget<Vec<u8>, A8> {
+    /// Creates an alpha only DrawTarget for building a `Mask`. Drawing with any source
+    /// only changes the coverage, so filling with `SrcOver` is a union and `DstOut` subtracts.
+    pub fn new_mask(width: i32, height: i32) -> Self {
+        DrawTarget::with_format(width, height)
+    }
 
-            output.push(r as u8);
-            output.push(g as u8);
-            output.push(b as u8);
-            output.push(a as u8);
+    /// Turns the coverage into a `Mask` that can be used with `DrawTarget::mask`
+    pub fn into_mask(self) -> Mask {
+        Mask {
+            width: self.width,
+            height: self.height,
+            data: self.buf,
         }
-
-        writer.write_image_data(&output)
     }
 }
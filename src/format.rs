@@ -0,0 +1,337 @@
+// Pixel formats that a DrawTarget can draw into. Most of the shading and blending is done
+// with premultiplied ARGB32 so formats only need to know how to convert rows of pixels
+// to and from that. Argb32 is the native format and is drawn into directly. Formats with
+// more than 8 bits per channel are drawn to with f32 components instead. See wide.rs.
+
+use sw_composite::{over_in, over_in_in};
+
+/// A format for the pixels of a `DrawTarget`. Rows of pixels are converted to premultiplied
+/// ARGB32, like: (A << 24) | (R << 16) | (G << 8) | B, before they are drawn to and
+/// converted back afterwards.
+pub trait PixelFormat {
+    type Pixel: Copy + Default;
+
+    /// Converts `src` to premultiplied ARGB32 in `dst`
+    fn load(src: &[Self::Pixel], dst: &mut [u32]);
+
+    /// Converts the premultiplied ARGB32 pixels in `src` to `dst`
+    fn store(src: &[u32], dst: &mut [Self::Pixel]);
+
+    /// Returns `pixels` as premultiplied ARGB32 if that's how they are stored so
+    /// that they can be drawn to without any conversion.
+    fn as_argb32(_pixels: &mut [Self::Pixel]) -> Option<&mut [u32]> {
+        None
+    }
+
+    /// Calls `f` with `pixels` as premultiplied ARGB32. Pixels that are stored some other
+    /// way are loaded into `scratch` and stored back once `f` returns.
+    fn with_argb32(pixels: &mut [Self::Pixel], scratch: &mut Vec<u32>, f: impl FnOnce(&mut [u32])) {
+        if let Some(pixels) = Self::as_argb32(pixels) {
+            return f(pixels);
+        }
+        scratch.resize(pixels.len(), 0);
+        Self::load(pixels, scratch);
+        f(scratch);
+        Self::store(scratch, pixels);
+    }
+
+    /// Draws `src` over `dst` with the coverage in `mask`, and in `clip` if there is one,
+    /// like `BlendMode::SrcOver`. Most drawing goes through this so formats that aren't
+    /// stored as ARGB32 can override it to blend their pixels directly.
+    fn over_in(src: &[u32], mask: &[u8], clip: Option<&[u8]>, dst: &mut [Self::Pixel], scratch: &mut Vec<u32>) {
+        Self::with_argb32(dst, scratch, |dst| match clip {
+            Some(clip) => {
+                for i in 0..dst.len() {
+                    let mask = mask[i] as u32;
+                    let clip = clip[i] as u32;
+                    if mask != 0 && clip != 0 {
+                        dst[i] = over_in_in(src[i], dst[i], mask, clip);
+                    }
+                }
+            }
+            None => {
+                for i in 0..dst.len() {
+                    let mask = mask[i] as u32;
+                    if mask != 0 {
+                        dst[i] = over_in(src[i], dst[i], mask);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Whether the format has more precision than ARGB32. Drawing to these formats is done
+    /// with `f32` components using `load_f32` and `store_f32` instead of `load` and `store`.
+    const HIGH_BIT_DEPTH: bool = false;
+
+    /// Converts `src` to premultiplied `[r, g, b, a]` floats in `dst`
+    fn load_f32(src: &[Self::Pixel], dst: &mut [[f32; 4]]) {
+        let mut tmp = [0; 64];
+        for (src, dst) in src.chunks(64).zip(dst.chunks_mut(64)) {
+            let tmp = &mut tmp[..src.len()];
+            Self::load(src, tmp);
+            for (dst, src) in dst.iter_mut().zip(tmp.iter()) {
+                *dst = argb32_to_f32(*src);
+            }
+        }
+    }
+
+    /// Converts the premultiplied `[r, g, b, a]` floats in `src` to `dst`
+    fn store_f32(src: &[[f32; 4]], dst: &mut [Self::Pixel]) {
+        let mut tmp = [0; 64];
+        for (src, dst) in src.chunks(64).zip(dst.chunks_mut(64)) {
+            let tmp = &mut tmp[..src.len()];
+            for (tmp, src) in tmp.iter_mut().zip(src) {
+                *tmp = f32_to_argb32(*src, 0.5);
+            }
+            Self::store(tmp, dst);
+        }
+    }
+}
+
+pub(crate) fn argb32_to_f32(pixel: u32) -> [f32; 4] {
+    [
+        ((pixel >> 16) & 0xff) as f32 / 255.,
+        ((pixel >> 8) & 0xff) as f32 / 255.,
+        (pixel & 0xff) as f32 / 255.,
+        (pixel >> 24) as f32 / 255.,
+    ]
+}
+
+/// Rounds `color` to ARGB32 by adding `offset` before truncating. An offset of 0.5 rounds
+/// to the nearest value and varying it between 0 and 1 dithers. Components are clamped
+/// so that the result is a valid premultiplied color.
+pub(crate) fn f32_to_argb32(color: [f32; 4], offset: f32) -> u32 {
+    // the cast truncates toward zero, which floors once negative values are clamped to 0
+    let quantize = |c: f32, max: u32| ((c * 255. + offset).max(0.) as u32).min(max);
+    let a = quantize(color[3], 255);
+    let r = quantize(color[0], a);
+    let g = quantize(color[1], a);
+    let b = quantize(color[2], a);
+    a << 24 | r << 16 | g << 8 | b
+}
+
+// Implements `PixelFormat::over_in` one pixel at a time for formats that are cheap to
+// convert pixel by pixel. Pixels without coverage aren't converted at all and opaque
+// pixels with full coverage are stored without reading `dst`.
+fn over_in_each<F: PixelFormat>(src: &[u32], mask: &[u8], clip: Option<&[u8]>, dst: &mut [F::Pixel]) {
+    for i in 0..dst.len() {
+        let (mask, clip) = (mask[i] as u32, clip.map_or(255, |clip| clip[i] as u32));
+        if mask == 0 || clip == 0 {
+            continue;
+        }
+        let dst = &mut dst[i..i + 1];
+        if mask == 255 && clip == 255 && src[i] >> 24 == 255 {
+            F::store(&src[i..i + 1], dst);
+            continue;
+        }
+        let mut pixel = [0];
+        F::load(dst, &mut pixel);
+        pixel[0] = if clip == 255 {
+            over_in(src[i], pixel[0], mask)
+        } else {
+            over_in_in(src[i], pixel[0], mask, clip)
+        };
+        F::store(&pixel, dst);
+    }
+}
+
+/// Premultiplied ARGB stored in native endian `u32`s. This is the default format.
+#[derive(Clone, Copy, Debug)]
+pub struct Argb32;
+
+impl PixelFormat for Argb32 {
+    type Pixel = u32;
+
+    fn load(src: &[u32], dst: &mut [u32]) {
+        dst.copy_from_slice(src);
+    }
+
+    fn store(src: &[u32], dst: &mut [u32]) {
+        dst.copy_from_slice(src);
+    }
+
+    fn as_argb32(pixels: &mut [u32]) -> Option<&mut [u32]> {
+        Some(pixels)
+    }
+}
+
+// Defines a premultiplied format with four 8 bit channels in the given byte order
+macro_rules! byte_format {
+    ($(#[$doc:meta])* $name:ident, $a:expr, $r:expr, $g:expr, $b:expr) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug)]
+        pub struct $name;
+
+        impl PixelFormat for $name {
+            type Pixel = [u8; 4];
+
+            fn load(src: &[[u8; 4]], dst: &mut [u32]) {
+                for (dst, src) in dst.iter_mut().zip(src) {
+                    *dst = (src[$a] as u32) << 24 | (src[$r] as u32) << 16 | (src[$g] as u32) << 8 | src[$b] as u32;
+                }
+            }
+
+            fn store(src: &[u32], dst: &mut [[u8; 4]]) {
+                for (dst, src) in dst.iter_mut().zip(src) {
+                    dst[$a] = (src >> 24) as u8;
+                    dst[$r] = (src >> 16) as u8;
+                    dst[$g] = (src >> 8) as u8;
+                    dst[$b] = *src as u8;
+                }
+            }
+        }
+    };
+}
+
+byte_format!(
+    /// Premultiplied RGBA with one byte per channel in that order. This is what
+    /// GPU APIs usually expect for texture uploads.
+    Rgba8, 3, 0, 1, 2
+);
+byte_format!(
+    /// Premultiplied BGRA with one byte per channel in that order. This matches the
+    /// memory layout of `Argb32` on little endian machines.
+    Bgra8, 3, 2, 1, 0
+);
+byte_format!(
+    /// Premultiplied ARGB with one byte per channel in that order. This matches the
+    /// memory layout of `Argb32` on big endian machines.
+    Argb8, 0, 1, 2, 3
+);
+
+/// Opaque 16 bit color with 5 bits of red, 6 bits of green and 5 bits of blue from
+/// the most to the least significant bit. Anything that isn't opaque is stored as
+/// if it had been drawn over black.
+#[derive(Clone, Copy, Debug)]
+pub struct Rgb565;
+
+impl PixelFormat for Rgb565 {
+    type Pixel = u16;
+
+    fn load(src: &[u16], dst: &mut [u32]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            let r = (src >> 11) as u32 & 0x1f;
+            let g = (src >> 5) as u32 & 0x3f;
+            let b = *src as u32 & 0x1f;
+            // replicate the high bits into the low bits so that full intensity stays full
+            let r = (r << 3) | (r >> 2);
+            let g = (g << 2) | (g >> 4);
+            let b = (b << 3) | (b >> 2);
+            *dst = 0xff00_0000 | r << 16 | g << 8 | b;
+        }
+    }
+
+    fn store(src: &[u32], dst: &mut [u16]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            // round to the nearest value instead of truncating, which would darken colors
+            let c = |shift: u32, max: u32| (((src >> shift) & 0xff) * max + 127) / 255;
+            let r = c(16, 0x1f);
+            let g = c(8, 0x3f);
+            let b = c(0, 0x1f);
+            *dst = (r << 11 | g << 5 | b) as u16;
+        }
+    }
+
+    fn over_in(src: &[u32], mask: &[u8], clip: Option<&[u8]>, dst: &mut [u16], _: &mut Vec<u32>) {
+        over_in_each::<Self>(src, mask, clip, dst);
+    }
+}
+
+/// Alpha only. Colors are dropped when storing and loaded pixels are black.
+#[derive(Clone, Copy, Debug)]
+pub struct A8;
+
+impl PixelFormat for A8 {
+    type Pixel = u8;
+
+    fn load(src: &[u8], dst: &mut [u32]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst = (*src as u32) << 24;
+        }
+    }
+
+    fn store(src: &[u32], dst: &mut [u8]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst = (src >> 24) as u8;
+        }
+    }
+
+    fn over_in(src: &[u32], mask: &[u8], clip: Option<&[u8]>, dst: &mut [u8], _: &mut Vec<u32>) {
+        over_in_each::<Self>(src, mask, clip, dst);
+    }
+}
+
+/// Premultiplied RGBA with 16 bits per channel in that order. This is drawn to with
+/// `f32` components so gradients and layers don't band like they do with 8 bits per channel.
+#[derive(Clone, Copy, Debug)]
+pub struct Rgba16;
+
+impl PixelFormat for Rgba16 {
+    type Pixel = [u16; 4];
+
+    const HIGH_BIT_DEPTH: bool = true;
+
+    fn load(src: &[[u16; 4]], dst: &mut [u32]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            let c = |i: usize| (src[i] as u32 * 255 + 32767) / 65535;
+            *dst = c(3) << 24 | c(0) << 16 | c(1) << 8 | c(2);
+        }
+    }
+
+    fn store(src: &[u32], dst: &mut [[u16; 4]]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            let c = |shift: u32| ((src >> shift) & 0xff) as u16 * 257;
+            *dst = [c(16), c(8), c(0), c(24)];
+        }
+    }
+
+    fn load_f32(src: &[[u16; 4]], dst: &mut [[f32; 4]]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            for i in 0..4 {
+                dst[i] = src[i] as f32 / 65535.;
+            }
+        }
+    }
+
+    fn store_f32(src: &[[f32; 4]], dst: &mut [[u16; 4]]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            let a = src[3].clamp(0., 1.);
+            for i in 0..3 {
+                dst[i] = (src[i].max(0.).min(a) * 65535. + 0.5) as u16;
+            }
+            dst[3] = (a * 65535. + 0.5) as u16;
+        }
+    }
+}
+
+/// Premultiplied RGBA stored as `f32`s in that order. Components aren't clamped so
+/// this can hold colors brighter than 1 for HDR content.
+#[derive(Clone, Copy, Debug)]
+pub struct RgbaF32;
+
+impl PixelFormat for RgbaF32 {
+    type Pixel = [f32; 4];
+
+    const HIGH_BIT_DEPTH: bool = true;
+
+    fn load(src: &[[f32; 4]], dst: &mut [u32]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst = f32_to_argb32(*src, 0.5);
+        }
+    }
+
+    fn store(src: &[u32], dst: &mut [[f32; 4]]) {
+        for (dst, src) in dst.iter_mut().zip(src) {
+            *dst = argb32_to_f32(*src);
+        }
+    }
+
+    fn load_f32(src: &[[f32; 4]], dst: &mut [[f32; 4]]) {
+        dst.copy_from_slice(src);
+    }
+
+    fn store_f32(src: &[[f32; 4]], dst: &mut [[f32; 4]]) {
+        dst.copy_from_slice(src);
+    }
+}
@@ -0,0 +1,186 @@
+// Evaluates gradients with f32 precision at every pixel instead of looking colors up in the
+// 256 entry table that sw_composite builds. The geometry of each kind of gradient follows
+// sw_composite so the results match apart from the extra precision.
+
+use sw_composite::{Color, Gradient, GradientStop, Spread};
+
+use crate::color::GradientColorSpace;
+use crate::draw_target::{GradientOptions, Source};
+use crate::{Point, Transform};
+
+use euclid::vec2;
+
+enum Geometry {
+    Linear,
+    Radial,
+    TwoCircle { c1: Point, r1: f32, c2: Point, r2: f32 },
+    Sweep { t_bias: f32, t_scale: f32 },
+}
+
+pub(crate) struct GradientF32 {
+    // stop positions and their unpremultiplied colors in `color_space`
+    stops: Vec<(f32, [f32; 4])>,
+    color_space: GradientColorSpace,
+    geometry: Geometry,
+    spread: Spread,
+    transform: Transform,
+    alpha: f32,
+}
+
+fn apply_spread(t: f32, spread: Spread) -> f32 {
+    match spread {
+        Spread::Pad => t.clamp(0., 1.),
+        Spread::Repeat => t - t.floor(),
+        Spread::Reflect => {
+            let t = t - 2. * (t / 2.).floor();
+            if t > 1. { 2. - t } else { t }
+        }
+    }
+}
+
+impl GradientF32 {
+    /// Returns None if `src` isn't a gradient. `ti` maps device space to user space.
+    pub fn new(src: &Source, ti: &Transform, alpha: f32) -> Option<GradientF32> {
+        GradientF32::with_options(src, ti, alpha, &GradientOptions::default())
+    }
+
+    fn with_options(src: &Source, ti: &Transform, alpha: f32, options: &GradientOptions) -> Option<GradientF32> {
+        let (gradient, spread, transform, geometry) = match *src {
+            Source::GradientWithOptions(ref with_options) => {
+                return GradientF32::with_options(&with_options.source, ti, alpha, &with_options.options)
+            }
+            Source::LinearGradient(ref gradient, spread, transform) => (gradient, spread, transform, Geometry::Linear),
+            Source::RadialGradient(ref gradient, spread, transform) => (gradient, spread, transform, Geometry::Radial),
+            Source::TwoCircleRadialGradient(ref gradient, spread, c1, r1, c2, r2, transform) => {
+                (gradient, spread, transform, Geometry::TwoCircle { c1, r1, c2, r2 })
+            }
+            Source::SweepGradient(ref gradient, spread, start_angle, end_angle, transform) => {
+                let t0 = start_angle / 360.;
+                let t1 = end_angle / 360.;
+                (gradient, spread, transform, Geometry::Sweep { t_bias: -t0, t_scale: 1. / (t1 - t0) })
+            }
+            _ => return None,
+        };
+        let color_space = options.color_space;
+        Some(GradientF32 {
+            stops: stops_f32(gradient).into_iter().map(|(position, color)| (position, color_space.convert_from_srgb(color))).collect(),
+            color_space,
+            geometry,
+            spread,
+            // sample at the center of the pixels
+            transform: ti.then(&transform).pre_translate(vec2(0.5, 0.5)),
+            alpha: alpha.clamp(0., 1.),
+        })
+    }
+
+    // Returns the position of the device pixel `x`, `y` in the gradient or None if
+    // the gradient doesn't cover it.
+    fn t(&self, x: i32, y: i32) -> Option<f32> {
+        let p = self.transform.transform_point(Point::new(x as f32, y as f32));
+        match self.geometry {
+            Geometry::Linear => Some(p.x),
+            Geometry::Radial => Some(p.to_vector().length()),
+            Geometry::TwoCircle { c1, r1, c2, r2 } => {
+                // the gradient is the family of circles ((1-t)·c₁ + t·c₂, (1-t)·r₁ + t·r₂)
+                // excluding those whose radius would be < 0. Later circles are drawn on top.
+                let cd = c2 - c1;
+                let pd = p - c1;
+                let dr = r2 - r1;
+                let a = cd.square_length() - dr * dr;
+                let b = pd.dot(cd) + r1 * dr;
+                let c = pd.square_length() - r1 * r1;
+                let radius = |t: f32| r1 + t * dr;
+                if a == 0. {
+                    let t = c / (2. * b);
+                    if radius(t) >= 0. { Some(t) } else { None }
+                } else {
+                    let discr = b * b - a * c;
+                    if discr < 0. {
+                        return None;
+                    }
+                    let t1 = (b + discr.sqrt()) / a;
+                    let t2 = (b - discr.sqrt()) / a;
+                    let (t1, t2) = if t1 > t2 { (t1, t2) } else { (t2, t1) };
+                    if radius(t1) >= 0. {
+                        Some(t1)
+                    } else if radius(t2) >= 0. {
+                        Some(t2)
+                    } else {
+                        None
+                    }
+                }
+            }
+            Geometry::Sweep { t_bias, t_scale } => {
+                let mut phi = p.y.atan2(p.x) / (2. * std::f32::consts::PI);
+                if phi < 0. {
+                    phi += 1.;
+                }
+                Some(phi * t_scale - t_bias)
+            }
+        }
+    }
+
+    /// Returns the premultiplied color at `t`. The spread is applied to `t` first.
+    pub fn color_at(&self, t: f32) -> [f32; 4] {
+        let t = apply_spread(t, self.spread);
+        let stops = &self.stops;
+        // the first stop past `t`. With duplicate positions, i.e. hard stops, this picks
+        // the color after the stop for `t` at the position.
+        let i = stops.partition_point(|&(position, _)| position <= t);
+        let color = if stops.is_empty() {
+            return [0.; 4];
+        } else if i == 0 {
+            stops[0].1
+        } else if i == stops.len() {
+            stops[i - 1].1
+        } else {
+            let (p0, c0) = stops[i - 1];
+            let (p1, c1) = stops[i];
+            self.color_space.mix(c0, c1, (t - p0) / (p1 - p0))
+        };
+        let color = self.color_space.convert_to_srgb(color);
+        let a = color[3] * self.alpha;
+        [color[0] * a, color[1] * a, color[2] * a, a]
+    }
+
+    pub fn shade_span(&self, x: i32, y: i32, dest: &mut [[f32; 4]]) {
+        for (i, dest) in dest.iter_mut().enumerate() {
+            *dest = match self.t(x + i as i32, y) {
+                Some(t) => self.color_at(t),
+                None => [0.; 4],
+            };
+        }
+    }
+}
+
+fn stops_f32(gradient: &Gradient) -> Vec<(f32, [f32; 4])> {
+    gradient.stops.iter().map(|stop| {
+        let c = stop.color;
+        (stop.position, [c.r() as f32 / 255., c.g() as f32 / 255., c.b() as f32 / 255., c.a() as f32 / 255.])
+    }).collect()
+}
+
+/// Returns a gradient with stops close enough together that sw_composite's interpolation
+/// between them follows the interpolation in `color_space`. This lets the lookup table be
+/// baked in any color space.
+pub(crate) fn resample(gradient: &Gradient, color_space: GradientColorSpace) -> Gradient {
+    let stops: Vec<_> = stops_f32(gradient).into_iter().map(|(position, color)| (position, color_space.convert_from_srgb(color))).collect();
+    let to_stop = |position: f32, color: [f32; 4]| {
+        let c = color_space.convert_to_srgb(color).map(|c| (c.clamp(0., 1.) * 255. + 0.5) as u8);
+        GradientStop { position, color: Color::new(c[3], c[0], c[1], c[2]) }
+    };
+    let mut resampled = Vec::new();
+    for pair in stops.windows(2) {
+        let ((p0, c0), (p1, c1)) = (pair[0], pair[1]);
+        // a stop for every entry of the table that the pair covers
+        let count = ((p1 - p0) * 255.).ceil().max(1.) as usize;
+        for i in 0..count {
+            let f = i as f32 / count as f32;
+            resampled.push(to_stop(p0 + (p1 - p0) * f, color_space.mix(c0, c1, f)));
+        }
+    }
+    if let Some(&(position, color)) = stops.last() {
+        resampled.push(to_stop(position, color));
+    }
+    Gradient { stops: resampled }
+}
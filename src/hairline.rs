@@ -0,0 +1,123 @@
+// Hairlines are one device pixel wide lines that are drawn directly into a coverage mask
+// instead of going through the stroker and the rasterizer. Antialiased lines are drawn
+// similarly to "An Efficient Antialiasing Technique" by Xiaolin Wu: for every column
+// (or row for steep lines) crossed by the line we split a pixel worth of coverage
+// between the two pixels closest to the line.
+
+use crate::path_builder::{Path, Segment};
+use crate::{IntRect, Point};
+
+/// Returns a coverage mask for `bounds` containing the hairlines for all of the
+/// segments of `path`. The path needs to be flattened and in device space.
+pub(crate) fn hairline_mask(path: &Path, bounds: IntRect, antialias: bool) -> Vec<u8> {
+    let mut mask = Mask {
+        data: vec![0; (bounds.width() * bounds.height()) as usize],
+        bounds,
+    };
+    for segment in path.segments() {
+        if let Segment::Line(line) = segment {
+            draw_line(&mut mask, line.from, line.to, antialias);
+        }
+    }
+    mask.data
+}
+
+struct Mask {
+    data: Vec<u8>,
+    bounds: IntRect,
+}
+
+impl Mask {
+    fn add(&mut self, x: i32, y: i32, coverage: f32) {
+        if x < self.bounds.min.x || x >= self.bounds.max.x || y < self.bounds.min.y || y >= self.bounds.max.y {
+            return;
+        }
+        let index = ((y - self.bounds.min.y) * self.bounds.width() + (x - self.bounds.min.x)) as usize;
+        // Coverage adds up so that a line split into segments in the middle of a pixel
+        // covers it once. Where segments overlap, like at the shared point of a polyline
+        // that turns, pixels can get up to twice the coverage of a single line.
+        let value = (coverage * 255. + 0.5) as u32;
+        self.data[index] = (self.data[index] as u32 + value).min(255) as u8;
+    }
+}
+
+fn draw_line(mask: &mut Mask, from: Point, to: Point, antialias: bool) {
+    let (from, to) = match clip_line(from, to, mask.bounds) {
+        Some(line) => line,
+        None => return,
+    };
+    let dx = to.x - from.x;
+    let dy = to.y - from.y;
+    if dx.abs() >= dy.abs() {
+        draw_major(from.x, from.y, to.x, to.y, antialias, &mut |x, y, coverage| mask.add(x, y, coverage));
+    } else {
+        // walk along y instead and swap the coordinates back when plotting
+        draw_major(from.y, from.x, to.y, to.x, antialias, &mut |y, x, coverage| mask.add(x, y, coverage));
+    }
+}
+
+// Clips the line to `bounds` expanded by a pixel, which is as far as pixels in `bounds` can
+// get coverage from, so that lines reaching far outside of the mask aren't walked a pixel at
+// a time. This is done in f64 so the clipped end points stay on the line. Returns None when
+// nothing is left.
+fn clip_line(from: Point, to: Point, bounds: IntRect) -> Option<(Point, Point)> {
+    let (x0, y0) = (from.x as f64, from.y as f64);
+    let (dx, dy) = (to.x as f64 - x0, to.y as f64 - y0);
+    let (min_x, min_y) = (bounds.min.x as f64 - 1., bounds.min.y as f64 - 1.);
+    let (max_x, max_y) = (bounds.max.x as f64 + 1., bounds.max.y as f64 + 1.);
+    // the range of t in `from + t * (to - from)` that is inside of each edge
+    let (mut t0, mut t1) = (0f64, 1f64);
+    for &(p, q) in &[(-dx, x0 - min_x), (dx, max_x - x0), (-dy, y0 - min_y), (dy, max_y - y0)] {
+        if p == 0. {
+            if q < 0. {
+                return None;
+            }
+        } else if p < 0. {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if t0 > t1 {
+        return None;
+    }
+    let point = |t: f64| Point::new((x0 + t * dx) as f32, (y0 + t * dy) as f32);
+    Some((point(t0), point(t1)))
+}
+
+// Draws a line that changes more along its major axis (u) than along its minor axis (v)
+fn draw_major(mut u0: f32, mut v0: f32, mut u1: f32, mut v1: f32, antialias: bool, plot: &mut dyn FnMut(i32, i32, f32)) {
+    if u0 == u1 {
+        return;
+    }
+    if u0 > u1 {
+        std::mem::swap(&mut u0, &mut u1);
+        std::mem::swap(&mut v0, &mut v1);
+    }
+    let slope = (v1 - v0) / (u1 - u0);
+    for u in u0.floor() as i32..u1.ceil() as i32 {
+        let left = (u as f32).max(u0);
+        let right = (u as f32 + 1.).min(u1);
+        if antialias {
+            // the end points only cover part of their pixels
+            let coverage = right - left;
+            if coverage <= 0. {
+                continue;
+            }
+            let center = (left + right) / 2.;
+            let v = v0 + (center - u0) * slope - 0.5;
+            let row = v.floor();
+            let fraction = v - row;
+            plot(u, row as i32, coverage * (1. - fraction));
+            plot(u, row as i32 + 1, coverage * fraction);
+        } else {
+            // only pixels whose centers are on the line are drawn
+            let center = u as f32 + 0.5;
+            if center < u0 || center >= u1 {
+                continue;
+            }
+            let v = v0 + (center - u0) * slope;
+            plot(u, v.floor() as i32, 1.);
+        }
+    }
+}
@@ -8,12 +8,15 @@ Current functionality
  - path filling
  - stroking
  - dashing
- - image, solid, and gradient fills
+ - image, solid, gradient, and mesh gradient fills
  - rectangular and path clipping
  - blend modes
  - layers
  - repeat modes for images
  - global alpha
+ - drawing into ARGB, RGBA, BGRA, RGB565 and A8 pixel formats
+ - 16 bit and floating point targets for high precision and HDR drawing
+ - blending in linear light
 
 ### Notable users
 - [resvg](https://github.com/RazrFalcon/resvg) supports using raqote as a backend.
@@ -99,23 +102,35 @@ Produces:
 #![warn(missing_copy_implementations)]
 
 mod blitter;
+mod color;
 mod dash;
 mod draw_target;
+mod format;
 mod geom;
+mod gradient;
+mod hairline;
+mod mesh;
 mod rasterizer;
+mod simplify;
 mod stroke;
 mod tests;
+mod wide;
 
 mod path_builder;
 pub use path_builder::*;
 
-pub use crate::draw_target::{AntialiasMode, FilterMode};
-pub use crate::draw_target::{BlendMode, DrawOptions, DrawTarget, SolidSource, Source, Winding, ExtendMode, Mask};
+pub use crate::color::{GradientColorSpace, HueInterpolation};
+pub use crate::draw_target::{AntialiasMode, CoverageOp, FilterMode, GradientEvaluation, GradientOptions};
+pub use crate::draw_target::{BlendMode, DrawOptions, DrawTarget, SolidSource, SolidSourceF32, Source, SourceWithOptions, Winding, ExtendMode, Mask};
+pub use crate::dash::{dash_path, dash_path_with_length};
+pub use crate::mesh::{MeshGradient, MeshPatch, MeshVertex};
+pub use crate::format::{PixelFormat, Argb32, Argb8, Bgra8, Rgba8, Rgb565, Rgba16, RgbaF32, A8};
 pub use crate::stroke::*;
 
 pub use sw_composite::{Color, Gradient, GradientStop, Image, Spread};
 
 pub type IntRect = euclid::default::Box2D<i32>;
+pub type Rect = euclid::default::Box2D<f32>;
 pub type IntPoint = euclid::default::Point2D<i32>;
 pub type Point = euclid::default::Point2D<f32>;
 pub type Transform = euclid::default::Transform2D<f32>;
@@ -0,0 +1,402 @@
+// Mesh gradients like the shading types 4 to 7 of PDF and the mesh gradients of SVG 2.
+// Patches are split into triangles that are flat in device space when a shader is built
+// and the triangles are shaded a span at a time. Later triangles are drawn over earlier
+// ones so pixels on shared edges get a single color and there are no seams. The triangles
+// are also put into bands of rows so that a span only looks at the ones that can cover it.
+
+use sw_composite::Color;
+
+use crate::blitter::Shader;
+use crate::wide::{row_to_argb32, WideShader};
+use crate::{IntRect, Point, Rect, Transform};
+
+/// A corner of a triangle in a `MeshGradient`
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct MeshVertex {
+    pub point: Point,
+    pub color: Color,
+}
+
+impl MeshVertex {
+    pub fn new(point: Point, color: Color) -> MeshVertex {
+        MeshVertex { point, color }
+    }
+}
+
+/// A tensor-product patch with a color at each corner. `points[i][j]` is the control
+/// point that is weighted by the i'th Bernstein polynomial of u and the j'th of v so
+/// the corners are `points[0][0]`, `points[0][3]`, `points[3][3]` and `points[3][0]`.
+/// `colors` are the colors of those corners in that order and are interpolated
+/// bilinearly in u and v.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct MeshPatch {
+    pub points: [[Point; 4]; 4],
+    pub colors: [Color; 4],
+}
+
+impl MeshPatch {
+    /// Creates a tensor-product patch from its 16 control points
+    pub fn new_tensor(points: [[Point; 4]; 4], colors: [Color; 4]) -> MeshPatch {
+        MeshPatch { points, colors }
+    }
+
+    /// Creates a Coons patch from the four cubic Bézier curves that bound it. The curves
+    /// go around the patch: `boundary[0..4]` is the first, `boundary[3..7]` the second,
+    /// `boundary[6..10]` the third and `boundary[9]`, `boundary[10]`, `boundary[11]` and
+    /// `boundary[0]` the fourth. `colors` are the colors at `boundary[0]`, `boundary[3]`,
+    /// `boundary[6]` and `boundary[9]`. This is the order used by PDF.
+    pub fn new_coons(boundary: [Point; 12], colors: [Color; 4]) -> MeshPatch {
+        let b = boundary;
+        let mut p = [
+            [b[0], b[1], b[2], b[3]],
+            [b[11], b[0], b[0], b[4]],
+            [b[10], b[0], b[0], b[5]],
+            [b[9], b[8], b[7], b[6]],
+        ];
+        // the interior control points that make the tensor-product patch a Coons patch
+        let interior = |w: &[(f32, Point)]| {
+            let sum = w.iter().fold(euclid::vec2(0., 0.), |sum, &(w, p)| sum + p.to_vector() * w);
+            (sum / 9.).to_point()
+        };
+        p[1][1] = interior(&[(-4., p[0][0]), (6., p[0][1]), (6., p[1][0]), (-2., p[0][3]), (-2., p[3][0]),
+                            (3., p[3][1]), (3., p[1][3]), (-1., p[3][3])]);
+        p[1][2] = interior(&[(-4., p[0][3]), (6., p[0][2]), (6., p[1][3]), (-2., p[0][0]), (-2., p[3][3]),
+                            (3., p[3][2]), (3., p[1][0]), (-1., p[3][0])]);
+        p[2][1] = interior(&[(-4., p[3][0]), (6., p[3][1]), (6., p[2][0]), (-2., p[3][3]), (-2., p[0][0]),
+                            (3., p[0][1]), (3., p[2][3]), (-1., p[0][3])]);
+        p[2][2] = interior(&[(-4., p[3][3]), (6., p[3][2]), (6., p[2][3]), (-2., p[3][0]), (-2., p[0][3]),
+                            (3., p[0][2]), (3., p[2][0]), (-1., p[0][0])]);
+        MeshPatch { points: p, colors }
+    }
+
+    /// Returns the point of the patch at `u`, `v`
+    pub fn point_at(&self, u: f32, v: f32) -> Point {
+        let (bu, bv) = (bernstein(u), bernstein(v));
+        let mut sum = euclid::vec2(0., 0.);
+        for (bu, row) in bu.iter().zip(&self.points) {
+            for (bv, point) in bv.iter().zip(row) {
+                sum += point.to_vector() * (bu * bv);
+            }
+        }
+        sum.to_point()
+    }
+
+    // The unpremultiplied color at `u`, `v`
+    fn color_at(&self, u: f32, v: f32) -> [f32; 4] {
+        let [c00, c03, c33, c30] = self.colors.map(color_to_f32);
+        let mut color = [0.; 4];
+        for k in 0..4 {
+            color[k] = (1. - u) * ((1. - v) * c00[k] + v * c03[k]) + u * ((1. - v) * c30[k] + v * c33[k]);
+        }
+        color
+    }
+}
+
+fn bernstein(t: f32) -> [f32; 4] {
+    let s = 1. - t;
+    [s * s * s, 3. * s * s * t, 3. * s * t * t, t * t * t]
+}
+
+fn color_to_f32(c: Color) -> [f32; 4] {
+    [c.r() as f32 / 255., c.g() as f32 / 255., c.b() as f32 / 255., c.a() as f32 / 255.]
+}
+
+/// A gradient made of triangles with a color at each vertex and of Coons and
+/// tensor-product patches with a color at each corner. Triangles are drawn before
+/// patches and later triangles and patches are drawn over earlier ones. Nothing is
+/// drawn outside of the mesh.
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct MeshGradient {
+    pub triangles: Vec<[MeshVertex; 3]>,
+    pub patches: Vec<MeshPatch>,
+}
+
+impl MeshGradient {
+    pub fn new() -> MeshGradient {
+        Default::default()
+    }
+
+    pub fn add_triangle(&mut self, a: MeshVertex, b: MeshVertex, c: MeshVertex) {
+        self.triangles.push([a, b, c]);
+    }
+
+    pub fn add_patch(&mut self, patch: MeshPatch) {
+        self.patches.push(patch);
+    }
+}
+
+// Patches are split in half in u and v until the pieces are within this many pixels of the
+// two triangles that they're drawn as and the colors at their corners are within
+// PATCH_COLOR_TOLERANCE of being linear. Pieces that can't cover any of the pixels being
+// drawn aren't split any further.
+const PATCH_TOLERANCE: f32 = 0.25;
+const PATCH_COLOR_TOLERANCE: f32 = 0.5 / 255.;
+// Bounds the number of pieces for patches that never become flat, like ones with NaNs
+const MAX_PATCH_DEPTH: u32 = 10;
+
+// The height of the bands of rows that triangles are put into. Bands get taller when the mesh
+// is tall compared to how many triangles it has.
+const BAND_HEIGHT: f32 = 8.;
+
+// How far outside of a triangle, in barycentric coordinates, a pixel center can be and
+// still be drawn. This keeps rounding from opening gaps between neighbouring triangles.
+const EDGE_TOLERANCE: f32 = 1e-4;
+
+struct Triangle {
+    // the barycentric coordinates of the vertices as `a * x + b * y + c` in device space
+    barycentric: [[f32; 3]; 3],
+    // unpremultiplied colors of the vertices
+    colors: [[f32; 4]; 3],
+    min_x: f32,
+    max_x: f32,
+    min_y: f32,
+    max_y: f32,
+}
+
+impl Triangle {
+    // Returns None for degenerate triangles, which cover no pixels
+    fn new(p: [Point; 3], colors: [[f32; 4]; 3]) -> Option<Triangle> {
+        let e1 = p[1] - p[0];
+        let e2 = p[2] - p[0];
+        let d = e1.cross(e2);
+        if d.abs() < f32::EPSILON || !d.is_finite() {
+            return None;
+        }
+        let l1 = [e2.y / d, -e2.x / d, (p[0].y * e2.x - p[0].x * e2.y) / d];
+        let l2 = [-e1.y / d, e1.x / d, (p[0].x * e1.y - p[0].y * e1.x) / d];
+        let l0 = [-l1[0] - l2[0], -l1[1] - l2[1], 1. - l1[2] - l2[2]];
+        Some(Triangle {
+            barycentric: [l0, l1, l2],
+            colors,
+            min_x: p[0].x.min(p[1].x).min(p[2].x),
+            max_x: p[0].x.max(p[1].x).max(p[2].x),
+            min_y: p[0].y.min(p[1].y).min(p[2].y),
+            max_y: p[0].y.max(p[1].y).max(p[2].y),
+        })
+    }
+
+    // Shades the pixels of the row at `y` from `x` to `x + dest.len()` whose centers are
+    // inside of the triangle
+    fn shade_span(&self, x: i32, y: f32, alpha: f32, dest: &mut [[f32; 4]]) {
+        if y < self.min_y || y > self.max_y || self.max_x < x as f32 || self.min_x > (x + dest.len() as i32) as f32 {
+            return;
+        }
+        // each coordinate is linear in x along the row so the inside is an interval
+        let (mut start, mut end) = (f32::NEG_INFINITY, f32::INFINITY);
+        let mut offsets = [0.; 3];
+        for (offset, &[a, b, c]) in offsets.iter_mut().zip(&self.barycentric) {
+            *offset = b * y + c;
+            if a == 0. {
+                if *offset < -EDGE_TOLERANCE {
+                    return;
+                }
+            } else if a > 0. {
+                start = start.max((-EDGE_TOLERANCE - *offset) / a);
+            } else {
+                end = end.min((-EDGE_TOLERANCE - *offset) / a);
+            }
+        }
+        // the pixels whose centers are in the interval
+        let first = (start - 0.5).ceil().max(x as f32) as i32;
+        let last = (end - 0.5).floor().min((x + dest.len() as i32 - 1) as f32) as i32;
+        for px in first..=last {
+            let center = px as f32 + 0.5;
+            let mut color = [0.; 4];
+            for (k, (&[a, ..], offset)) in self.barycentric.iter().zip(offsets).enumerate() {
+                let weight = (a * center + offset).max(0.);
+                for (c, vertex) in color.iter_mut().zip(&self.colors[k]) {
+                    *c += vertex * weight;
+                }
+            }
+            let a = color[3].clamp(0., 1.) * alpha;
+            let c = |c: f32| c.clamp(0., 1.) * a;
+            dest[(px - x) as usize] = [c(color[0]), c(color[1]), c(color[2]), a];
+        }
+    }
+}
+
+// A piece of a patch that is flat enough to be drawn as two triangles
+struct PatchPiece {
+    // where the piece starts in the patch, for sorting
+    u: f32,
+    v: f32,
+    // the corners at (u0, v0), (u1, v0), (u0, v1) and (u1, v1) in device space
+    corners: [Point; 4],
+    colors: [[f32; 4]; 4],
+}
+
+// Splits the cubic `p` in half with de Casteljau's algorithm
+fn split_cubic(p: [Point; 4]) -> ([Point; 4], [Point; 4]) {
+    let mid = |a: Point, b: Point| a.lerp(b, 0.5);
+    let (p01, p12, p23) = (mid(p[0], p[1]), mid(p[1], p[2]), mid(p[2], p[3]));
+    let (p012, p123) = (mid(p01, p12), mid(p12, p23));
+    let p0123 = mid(p012, p123);
+    ([p[0], p01, p012, p0123], [p0123, p123, p23, p[3]])
+}
+
+// How far the patch with the control points `p` can be from the two triangles it's drawn as.
+// The control points of the bilinear patch through the corners are the corners interpolated
+// at thirds so the patch is within the largest distance between the two sets of control
+// points of that, which in turn is within a quarter of its twist of the triangles.
+fn patch_flatness(p: &[[Point; 4]; 4]) -> f32 {
+    let bilinear = |u: f32, v: f32| {
+        let top = p[0][0].lerp(p[0][3], v);
+        let bottom = p[3][0].lerp(p[3][3], v);
+        top.lerp(bottom, u)
+    };
+    let mut distance: f32 = 0.;
+    for (i, row) in p.iter().enumerate() {
+        for (j, &point) in row.iter().enumerate() {
+            distance = distance.max((point - bilinear(i as f32 / 3., j as f32 / 3.)).length());
+        }
+    }
+    let twist = (p[0][0] - p[0][3]) - (p[3][0] - p[3][3]);
+    distance + twist.length() / 4.
+}
+
+// Adds the pieces of the part of `patch` from `u[0]` to `u[1]` and `v[0]` to `v[1]`, whose
+// control points in device space are `points`, to `pieces`
+fn split_patch(patch: &MeshPatch, points: [[Point; 4]; 4], u: [f32; 2], v: [f32; 2], bounds: &Rect, depth: u32, pieces: &mut Vec<PatchPiece>) {
+    // the part is inside of the hull of its control points
+    let (mut min, mut max) = (points[0][0], points[0][0]);
+    for &p in points.iter().flatten() {
+        min = min.min(p);
+        max = max.max(p);
+    }
+    if max.x < bounds.min.x || max.y < bounds.min.y || min.x > bounds.max.x || min.y > bounds.max.y {
+        return;
+    }
+
+    let colors = [
+        patch.color_at(u[0], v[0]),
+        patch.color_at(u[1], v[0]),
+        patch.color_at(u[0], v[1]),
+        patch.color_at(u[1], v[1]),
+    ];
+    let color_twist = (0..4).fold(0., |twist: f32, k| {
+        twist.max((colors[0][k] - colors[1][k] - colors[2][k] + colors[3][k]).abs() / 4.)
+    });
+    let flatness = patch_flatness(&points);
+    // comparisons with NaN are false so those pieces end up here too and are dropped as degenerate
+    if depth == MAX_PATCH_DEPTH || !(flatness > PATCH_TOLERANCE || color_twist > PATCH_COLOR_TOLERANCE) {
+        pieces.push(PatchPiece {
+            u: u[0],
+            v: v[0],
+            corners: [points[0][0], points[3][0], points[0][3], points[3][3]],
+            colors,
+        });
+        return;
+    }
+
+    // split each curve in u, the columns, and then each curve in v, the rows
+    let (mut low_u, mut high_u) = (points, points);
+    for j in 0..4 {
+        let (low, high) = split_cubic([points[0][j], points[1][j], points[2][j], points[3][j]]);
+        for i in 0..4 {
+            low_u[i][j] = low[i];
+            high_u[i][j] = high[i];
+        }
+    }
+    let (mid_u, mid_v) = ((u[0] + u[1]) / 2., (v[0] + v[1]) / 2.);
+    for (half, u) in [(low_u, [u[0], mid_u]), (high_u, [mid_u, u[1]])] {
+        let (mut low_v, mut high_v) = (half, half);
+        for i in 0..4 {
+            let (low, high) = split_cubic(half[i]);
+            low_v[i] = low;
+            high_v[i] = high;
+        }
+        split_patch(patch, low_v, u, [v[0], mid_v], bounds, depth + 1, pieces);
+        split_patch(patch, high_v, u, [mid_v, v[1]], bounds, depth + 1, pieces);
+    }
+}
+
+/// Shades a `MeshGradient` with f32 precision
+pub struct MeshShader {
+    triangles: Vec<Triangle>,
+    // the indices of the triangles that overlap each band, in drawing order
+    bands: Vec<Vec<u32>>,
+    band_top: f32,
+    band_height: f32,
+    alpha: f32,
+}
+
+impl MeshShader {
+    /// `ti` maps device space to the space of the mesh. Patches are only split finely
+    /// where they can cover `bounds`, which is in device space.
+    pub fn new(mesh: &MeshGradient, ti: &Transform, alpha: f32, bounds: IntRect) -> MeshShader {
+        let mut triangles = Vec::new();
+        let transform = match ti.inverse() {
+            Some(transform) => transform,
+            // nothing is drawn when the mesh collapses
+            None => return MeshShader::with_bands(triangles, alpha),
+        };
+        let to_device = |p: Point| transform.transform_point(p);
+
+        for vertices in &mesh.triangles {
+            let points = vertices.map(|v| to_device(v.point));
+            triangles.extend(Triangle::new(points, vertices.map(|v| color_to_f32(v.color))));
+        }
+
+        // pixel centers just outside of the bounds don't matter but keep a margin for rounding
+        let bounds = bounds.to_f32().inflate(1., 1.);
+        for patch in &mesh.patches {
+            let points = patch.points.map(|row| row.map(to_device));
+            let mut pieces = Vec::new();
+            split_patch(patch, points, [0., 1.], [0., 1.], &bounds, 0, &mut pieces);
+            // Where a patch folds over itself the parts with larger v, and then larger u,
+            // are drawn on top like PDF specifies
+            pieces.sort_by(|a, b| (a.v, a.u).partial_cmp(&(b.v, b.u)).unwrap());
+            for piece in pieces {
+                let [p00, p10, p01, p11] = piece.corners;
+                let [c00, c10, c01, c11] = piece.colors;
+                triangles.extend(Triangle::new([p00, p10, p11], [c00, c10, c11]));
+                triangles.extend(Triangle::new([p00, p11, p01], [c00, c11, c01]));
+            }
+        }
+
+        MeshShader::with_bands(triangles, alpha.clamp(0., 1.))
+    }
+
+    fn with_bands(triangles: Vec<Triangle>, alpha: f32) -> MeshShader {
+        if triangles.is_empty() {
+            return MeshShader { triangles, bands: Vec::new(), band_top: 0., band_height: BAND_HEIGHT, alpha };
+        }
+        let top = triangles.iter().fold(f32::INFINITY, |top, t| top.min(t.min_y));
+        let bottom = triangles.iter().fold(f32::NEG_INFINITY, |bottom, t| bottom.max(t.max_y));
+        let band_height = BAND_HEIGHT.max((bottom - top) / triangles.len() as f32);
+        let band = |y: f32| ((y - top) / band_height) as usize;
+        let mut bands = vec![Vec::new(); band(bottom) + 1];
+        for (i, triangle) in triangles.iter().enumerate() {
+            for band in &mut bands[band(triangle.min_y)..=band(triangle.max_y)] {
+                band.push(i as u32);
+            }
+        }
+        MeshShader { triangles, bands, band_top: top, band_height, alpha }
+    }
+}
+
+impl WideShader for MeshShader {
+    fn shade_span_wide(&self, x: i32, y: i32, dest: &mut [[f32; 4]]) {
+        dest.iter_mut().for_each(|pixel| *pixel = [0.; 4]);
+        let y = y as f32 + 0.5;
+        if y < self.band_top {
+            return;
+        }
+        let band = ((y - self.band_top) / self.band_height) as usize;
+        for &i in self.bands.get(band).into_iter().flatten() {
+            self.triangles[i as usize].shade_span(x, y, self.alpha, dest);
+        }
+    }
+}
+
+impl Shader for MeshShader {
+    fn shade_span(&self, x: i32, y: i32, dest: &mut [u32], count: usize) {
+        let mut tmp = [[0.; 4]; 64];
+        for (i, dest) in dest[..count].chunks_mut(tmp.len()).enumerate() {
+            let x = x + (i * tmp.len()) as i32;
+            let tmp = &mut tmp[..dest.len()];
+            self.shade_span_wide(x, y, tmp);
+            row_to_argb32(tmp, dest, x, y, false);
+        }
+    }
+}
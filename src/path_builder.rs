@@ -1,17 +1,43 @@
 use lyon_geom::Angle;
 use lyon_geom::Arc;
 use lyon_geom::CubicBezierSegment;
+use lyon_geom::LineSegment;
 use lyon_geom::QuadraticBezierSegment;
 
-use crate::{Point, Transform, Vector};
+use crate::stroke::{dash_and_stroke, LineCap, LineJoin, StrokeStyle};
+use crate::{Point, Rect, Transform, Vector};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Winding {
     EvenOdd,
     NonZero,
+    /// Paints everything that `EvenOdd` doesn't
+    InverseEvenOdd,
+    /// Paints everything that `NonZero` doesn't
+    InverseNonZero,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Winding {
+    /// Returns true if `self` paints the area outside of the path
+    pub fn is_inverse(self) -> bool {
+        match self {
+            Winding::EvenOdd | Winding::NonZero => false,
+            Winding::InverseEvenOdd | Winding::InverseNonZero => true,
+        }
+    }
+
+    /// Returns whether points with a winding number of `winding` are painted
+    pub(crate) fn is_inside(self, winding: i32) -> bool {
+        match self {
+            Winding::EvenOdd => winding & 1 != 0,
+            Winding::NonZero => winding != 0,
+            Winding::InverseEvenOdd => winding & 1 == 0,
+            Winding::InverseNonZero => winding == 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum PathOp {
     MoveTo(Point),
     LineTo(Point),
@@ -46,7 +72,246 @@ pub struct Path {
     pub winding: Winding,
 }
 
+/// A single segment of a `Path` along with its start point.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Segment {
+    Line(LineSegment<f32>),
+    Quadratic(QuadraticBezierSegment<f32>),
+    Cubic(CubicBezierSegment<f32>),
+}
+
+/// An iterator over the segments of a `Path`. Created by `Path::segments`.
+pub struct Segments<'a> {
+    ops: std::slice::Iter<'a, PathOp>,
+    first_point: Option<Point>,
+    cur_pt: Option<Point>,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = Segment;
+
+    fn next(&mut self) -> Option<Segment> {
+        for op in &mut self.ops {
+            match *op {
+                PathOp::MoveTo(pt) => {
+                    self.first_point = Some(pt);
+                    self.cur_pt = Some(pt);
+                }
+                PathOp::LineTo(pt) => {
+                    if let Some(cur_pt) = self.cur_pt.replace(pt) {
+                        return Some(Segment::Line(LineSegment { from: cur_pt, to: pt }));
+                    }
+                    self.first_point = Some(pt);
+                }
+                PathOp::QuadTo(cpt, pt) => {
+                    let from = self.cur_pt.unwrap_or(cpt);
+                    self.first_point = self.first_point.or(Some(from));
+                    self.cur_pt = Some(pt);
+                    return Some(Segment::Quadratic(QuadraticBezierSegment { from, ctrl: cpt, to: pt }));
+                }
+                PathOp::CubicTo(cpt1, cpt2, pt) => {
+                    let from = self.cur_pt.unwrap_or(cpt1);
+                    self.first_point = self.first_point.or(Some(from));
+                    self.cur_pt = Some(pt);
+                    return Some(Segment::Cubic(CubicBezierSegment { from, ctrl1: cpt1, ctrl2: cpt2, to: pt }));
+                }
+                PathOp::Close => {
+                    // like DrawTarget, closing moves the current point back to the start of the subpath
+                    if let (Some(first_point), Some(cur_pt)) = (self.first_point, self.cur_pt) {
+                        self.cur_pt = Some(first_point);
+                        if cur_pt != first_point {
+                            return Some(Segment::Line(LineSegment { from: cur_pt, to: first_point }));
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+impl Segment {
+    /// The start point of the segment
+    pub fn from(&self) -> Point {
+        match self {
+            Segment::Line(l) => l.from,
+            Segment::Quadratic(q) => q.from,
+            Segment::Cubic(c) => c.from,
+        }
+    }
+
+    /// The end point of the segment
+    pub fn to(&self) -> Point {
+        match self {
+            Segment::Line(l) => l.to,
+            Segment::Quadratic(q) => q.to,
+            Segment::Cubic(c) => c.to,
+        }
+    }
+}
+
+impl std::iter::FromIterator<PathOp> for Path {
+    fn from_iter<I: IntoIterator<Item = PathOp>>(iter: I) -> Path {
+        Path { ops: iter.into_iter().collect(), winding: Winding::NonZero }
+    }
+}
+
+impl Extend<PathOp> for Path {
+    fn extend<I: IntoIterator<Item = PathOp>>(&mut self, iter: I) {
+        self.ops.extend(iter)
+    }
+}
+
 impl Path {
+    /// Returns an iterator over the line and curve segments of `self`. Closing a
+    /// subpath yields the line back to its start point if it has non-zero length.
+    pub fn segments(&self) -> Segments<'_> {
+        Segments { ops: self.ops.iter(), first_point: None, cur_pt: None }
+    }
+
+    /// Calls `f` with the ops of `self` rewritten so that every subpath starts with
+    /// an explicit `MoveTo`. `Path` allows drawing without a current point but
+    /// other path representations don't.
+    fn for_each_explicit_op(&self, mut f: impl FnMut(PathOp)) {
+        let mut first_point = None;
+        let mut cur_pt = None;
+        let mut open = false;
+        for op in &self.ops {
+            let (start, end) = match *op {
+                PathOp::MoveTo(pt) => {
+                    first_point = Some(pt);
+                    cur_pt = Some(pt);
+                    open = true;
+                    f(*op);
+                    continue;
+                }
+                PathOp::Close => {
+                    if open {
+                        f(*op);
+                        open = false;
+                        cur_pt = first_point;
+                    }
+                    continue;
+                }
+                PathOp::LineTo(pt) => (pt, pt),
+                PathOp::QuadTo(cpt, pt) => (cpt, pt),
+                PathOp::CubicTo(cpt1, _, pt) => (cpt1, pt),
+            };
+            match cur_pt {
+                None => {
+                    f(PathOp::MoveTo(start));
+                    first_point = Some(start);
+                }
+                Some(cur_pt) if !open => f(PathOp::MoveTo(cur_pt)),
+                _ => {}
+            }
+            open = true;
+            // a LineTo without a current point only sets the current point
+            if cur_pt.is_some() || !matches!(op, PathOp::LineTo(..)) {
+                f(*op);
+            }
+            cur_pt = Some(end);
+        }
+    }
+
+    /// Splits `self` into a separate `Path` for each subpath. Every returned contour
+    /// starts with a `MoveTo` and has the winding of `self`. Subpaths that don't
+    /// contain any segments are dropped.
+    pub fn contours(&self) -> Vec<Path> {
+        let mut contours = Vec::new();
+        let mut current: Vec<PathOp> = Vec::new();
+        let winding = self.winding;
+        let mut flush = |current: &mut Vec<PathOp>| {
+            if current.len() > 1 {
+                contours.push(Path { ops: std::mem::take(current), winding });
+            } else {
+                current.clear();
+            }
+        };
+        self.for_each_explicit_op(|op| {
+            if let PathOp::MoveTo(_) = op {
+                flush(&mut current);
+            }
+            current.push(op);
+        });
+        flush(&mut current);
+        contours
+    }
+
+    /// Returns a path with the direction of every subpath reversed. Closed subpaths
+    /// keep their start point, open subpaths start at their old end point.
+    pub fn reverse(&self) -> Path {
+        let mut ops = Vec::with_capacity(self.ops.len());
+        for contour in self.contours() {
+            let closed = matches!(contour.ops.last(), Some(PathOp::Close));
+            let segments: Vec<Segment> = contour.segments().collect();
+            let start = match (closed, &contour.ops[0], segments.last()) {
+                (true, PathOp::MoveTo(pt), _) => *pt,
+                (false, _, Some(last)) => last.to(),
+                _ => continue,
+            };
+            ops.push(PathOp::MoveTo(start));
+            for segment in segments.iter().rev() {
+                ops.push(match *segment {
+                    Segment::Line(l) => PathOp::LineTo(l.from),
+                    Segment::Quadratic(q) => PathOp::QuadTo(q.ctrl, q.from),
+                    Segment::Cubic(c) => PathOp::CubicTo(c.ctrl2, c.ctrl1, c.from),
+                });
+            }
+            if closed {
+                ops.push(PathOp::Close);
+            }
+        }
+        Path { ops, winding: self.winding }
+    }
+
+    /// Returns the signed area enclosed by `self`. Every subpath is treated as
+    /// closed, as it would be when filling. In raqote's y-down coordinate space
+    /// clockwise subpaths have a positive area and counter-clockwise subpaths a
+    /// negative one. Overlapping subpaths are summed.
+    pub fn signed_area(&self) -> f32 {
+        fn cross(a: Point, b: Point) -> f32 {
+            a.x * b.y - a.y * b.x
+        }
+
+        let mut area = 0.;
+        for contour in self.contours() {
+            let mut first_point = None;
+            let mut last_point = None;
+            for segment in contour.segments() {
+                first_point = first_point.or(Some(segment.from()));
+                last_point = Some(segment.to());
+                // Green's theorem applied to each segment. The curve formulas
+                // are the closed forms of the integral of x dy - y dx.
+                area += match segment {
+                    Segment::Line(l) => cross(l.from, l.to) / 2.,
+                    Segment::Quadratic(q) => {
+                        let (p0, p1, p2) = (q.from, q.ctrl, q.to);
+                        (p0.x * (2. * p1.y + p2.y) + 2. * p1.x * (p2.y - p0.y) - p2.x * (p0.y + 2. * p1.y)) / 6.
+                    }
+                    Segment::Cubic(c) => {
+                        let (p0, p1, p2, p3) = (c.from, c.ctrl1, c.ctrl2, c.to);
+                        (p0.x * (6. * p1.y + 3. * p2.y + p3.y)
+                            + 3. * (p1.x * (-2. * p0.y + p2.y + p3.y) - p2.x * (p0.y + p1.y - 2. * p3.y))
+                            - p3.x * (p0.y + 3. * p1.y + 6. * p2.y)) / 20.
+                    }
+                };
+            }
+            // the implicit closing segment
+            if let (Some(first_point), Some(last_point)) = (first_point, last_point) {
+                area += cross(last_point, first_point) / 2.;
+            }
+        }
+        area
+    }
+
+    /// Returns true if the signed area of `self` is positive. i.e. the path runs
+    /// clockwise in raqote's y-down coordinate space. Use `contours` to query
+    /// the direction of individual subpaths.
+    pub fn is_clockwise(&self) -> bool {
+        self.signed_area() > 0.
+    }
+
     /// Flattens `self` by replacing all QuadTo and CurveTo
     /// commands with an appropriate number of LineTo commands
     /// so that the error is not greater than `tolerance`.
@@ -194,11 +459,14 @@ impl Path {
         // make sure the path is closed
         ws.close();
 
-        let inside = match self.winding {
-            Winding::EvenOdd => ws.count & 1 != 0,
-            Winding::NonZero => ws.count != 0,
-        };
-        inside || ws.on_edge
+        self.winding.is_inside(ws.count) || ws.on_edge
+    }
+
+    /// Returns true if the point `x`, `y` is within the area that would be painted
+    /// by stroking `self` with `style`. This is the equivalent of Canvas' `isPointInStroke`.
+    /// The path and the resulting stroke will be flattened using `tolerance`.
+    pub fn contains_point_in_stroke(&self, style: &StrokeStyle, tolerance: f32, x: f32, y: f32) -> bool {
+        dash_and_stroke(self, style, tolerance).contains_point(tolerance, x, y)
     }
 
     pub fn transform(self, transform: &Transform) -> Path {
@@ -206,6 +474,121 @@ impl Path {
         let ops = ops.into_iter().map(|op| op.transform(transform)).collect();
         Path { ops, winding }
     }
+
+    /// Returns the bounding box of all of the points of `self` including the
+    /// control points of curves. This is cheap to compute but can be larger
+    /// than the area covered by the path. Returns `None` for an empty path.
+    pub fn control_bounds(&self) -> Option<Rect> {
+        let mut bounds = None;
+        for op in &self.ops {
+            match *op {
+                PathOp::MoveTo(pt) | PathOp::LineTo(pt) => union_point(&mut bounds, pt),
+                PathOp::QuadTo(cpt, pt) => {
+                    union_point(&mut bounds, cpt);
+                    union_point(&mut bounds, pt);
+                }
+                PathOp::CubicTo(cpt1, cpt2, pt) => {
+                    union_point(&mut bounds, cpt1);
+                    union_point(&mut bounds, cpt2);
+                    union_point(&mut bounds, pt);
+                }
+                PathOp::Close => {}
+            }
+        }
+        bounds
+    }
+
+    /// Returns the smallest box that contains `self`. Unlike `control_bounds`
+    /// the extrema of curves are solved for exactly, so control points that lie
+    /// outside of the curve don't contribute. Returns `None` for an empty path.
+    pub fn bounds(&self) -> Option<Rect> {
+        let mut cur_pt = None;
+        let mut bounds = None;
+        for op in &self.ops {
+            match *op {
+                PathOp::MoveTo(pt) | PathOp::LineTo(pt) => {
+                    union_point(&mut bounds, pt);
+                    cur_pt = Some(pt);
+                }
+                PathOp::QuadTo(cpt, pt) => {
+                    let c = QuadraticBezierSegment {
+                        from: cur_pt.unwrap_or(cpt),
+                        ctrl: cpt,
+                        to: pt,
+                    };
+                    union_rect(&mut bounds, c.bounding_box());
+                    cur_pt = Some(pt);
+                }
+                PathOp::CubicTo(cpt1, cpt2, pt) => {
+                    let c = CubicBezierSegment {
+                        from: cur_pt.unwrap_or(cpt1),
+                        ctrl1: cpt1,
+                        ctrl2: cpt2,
+                        to: pt,
+                    };
+                    union_rect(&mut bounds, c.bounding_box());
+                    cur_pt = Some(pt);
+                }
+                PathOp::Close => {}
+            }
+        }
+        bounds
+    }
+
+    /// Returns the tight bounds of `self` after `transform` has been applied to it.
+    pub fn transformed_bounds(&self, transform: &Transform) -> Option<Rect> {
+        // Affine transforms map bezier curves to bezier curves so we can just
+        // transform the control points and solve for the extrema afterwards.
+        self.clone().transform(transform).bounds()
+    }
+
+    /// Returns a conservative bounding box of the area painted when stroking `self`
+    /// with `style` and then applying `transform`. The outset accounts for the
+    /// stroke width, miter joins and square caps.
+    pub fn stroke_bounds(&self, style: &StrokeStyle, transform: &Transform) -> Option<Rect> {
+        if style.width <= 0. {
+            return None;
+        }
+        let bounds = self.bounds()?;
+
+        // This matches the inflation radius used by Skia
+        let mut multiplier = 1f32;
+        match style.join {
+            LineJoin::Miter => multiplier = multiplier.max(style.miter_limit),
+            // the corners of a clipped miter can be up to half the width beside the clip line
+            LineJoin::MiterClip | LineJoin::Arcs => multiplier = multiplier.max(style.miter_limit.hypot(1.)),
+            LineJoin::Round | LineJoin::Bevel => {}
+        }
+        if style.cap == LineCap::Square {
+            multiplier = multiplier.max(std::f32::consts::SQRT_2);
+        }
+        let outset = style.width / 2. * multiplier;
+        let bounds = bounds.inflate(outset, outset);
+
+        let corners = [
+            bounds.min,
+            Point::new(bounds.max.x, bounds.min.y),
+            bounds.max,
+            Point::new(bounds.min.x, bounds.max.y),
+        ];
+        let mut result = None;
+        for corner in &corners {
+            union_point(&mut result, transform.transform_point(*corner));
+        }
+        result
+    }
+}
+
+fn union_point(bounds: &mut Option<Rect>, pt: Point) {
+    union_rect(bounds, Rect::new(pt, pt));
+}
+
+// Box2D::union ignores empty boxes so we can't use it for accumulating points
+fn union_rect(bounds: &mut Option<Rect>, rect: Rect) {
+    *bounds = Some(match *bounds {
+        Some(b) => Rect::new(b.min.min(rect.min), b.max.max(rect.max)),
+        None => rect,
+    });
 }
 
 /// A helper struct used for constructing a `Path`.
@@ -299,3 +682,125 @@ impl PathBuilder {
         self.path
     }
 }
+
+#[cfg(feature = "lyon_path")]
+mod lyon_conversions {
+    use super::{Path, PathOp, Winding};
+
+    impl From<&Path> for lyon_path::Path {
+        fn from(path: &Path) -> Self {
+            let mut builder = lyon_path::Path::builder();
+            let mut open = false;
+            path.for_each_explicit_op(|op| match op {
+                PathOp::MoveTo(pt) => {
+                    if open {
+                        builder.end(false);
+                    }
+                    builder.begin(pt);
+                    open = true;
+                }
+                PathOp::LineTo(pt) => {
+                    builder.line_to(pt);
+                }
+                PathOp::QuadTo(cpt, pt) => {
+                    builder.quadratic_bezier_to(cpt, pt);
+                }
+                PathOp::CubicTo(cpt1, cpt2, pt) => {
+                    builder.cubic_bezier_to(cpt1, cpt2, pt);
+                }
+                PathOp::Close => {
+                    builder.end(true);
+                    open = false;
+                }
+            });
+            if open {
+                builder.end(false);
+            }
+            builder.build()
+        }
+    }
+
+    impl From<Path> for lyon_path::Path {
+        fn from(path: Path) -> Self {
+            (&path).into()
+        }
+    }
+
+    impl From<&lyon_path::Path> for Path {
+        fn from(path: &lyon_path::Path) -> Self {
+            use lyon_path::Event;
+            let mut ops = Vec::new();
+            for event in path.iter() {
+                match event {
+                    Event::Begin { at } => ops.push(PathOp::MoveTo(at)),
+                    Event::Line { to, .. } => ops.push(PathOp::LineTo(to)),
+                    Event::Quadratic { ctrl, to, .. } => ops.push(PathOp::QuadTo(ctrl, to)),
+                    Event::Cubic { ctrl1, ctrl2, to, .. } => ops.push(PathOp::CubicTo(ctrl1, ctrl2, to)),
+                    Event::End { close: true, .. } => ops.push(PathOp::Close),
+                    Event::End { close: false, .. } => {}
+                }
+            }
+            Path { ops, winding: Winding::NonZero }
+        }
+    }
+
+    impl From<lyon_path::Path> for Path {
+        fn from(path: lyon_path::Path) -> Self {
+            (&path).into()
+        }
+    }
+}
+
+#[cfg(feature = "kurbo")]
+mod kurbo_conversions {
+    use super::{Path, PathOp, Winding};
+    use crate::Point;
+    use kurbo::{BezPath, PathEl};
+
+    fn to_kurbo(pt: Point) -> kurbo::Point {
+        kurbo::Point::new(pt.x as f64, pt.y as f64)
+    }
+
+    fn from_kurbo(pt: kurbo::Point) -> Point {
+        Point::new(pt.x as f32, pt.y as f32)
+    }
+
+    impl From<&Path> for BezPath {
+        fn from(path: &Path) -> Self {
+            let mut bez = BezPath::new();
+            path.for_each_explicit_op(|op| bez.push(match op {
+                PathOp::MoveTo(pt) => PathEl::MoveTo(to_kurbo(pt)),
+                PathOp::LineTo(pt) => PathEl::LineTo(to_kurbo(pt)),
+                PathOp::QuadTo(cpt, pt) => PathEl::QuadTo(to_kurbo(cpt), to_kurbo(pt)),
+                PathOp::CubicTo(cpt1, cpt2, pt) => PathEl::CurveTo(to_kurbo(cpt1), to_kurbo(cpt2), to_kurbo(pt)),
+                PathOp::Close => PathEl::ClosePath,
+            }));
+            bez
+        }
+    }
+
+    impl From<Path> for BezPath {
+        fn from(path: Path) -> Self {
+            (&path).into()
+        }
+    }
+
+    impl From<&BezPath> for Path {
+        fn from(bez: &BezPath) -> Self {
+            let ops = bez.elements().iter().map(|el| match *el {
+                PathEl::MoveTo(pt) => PathOp::MoveTo(from_kurbo(pt)),
+                PathEl::LineTo(pt) => PathOp::LineTo(from_kurbo(pt)),
+                PathEl::QuadTo(cpt, pt) => PathOp::QuadTo(from_kurbo(cpt), from_kurbo(pt)),
+                PathEl::CurveTo(cpt1, cpt2, pt) => PathOp::CubicTo(from_kurbo(cpt1), from_kurbo(cpt2), from_kurbo(pt)),
+                PathEl::ClosePath => PathOp::Close,
+            }).collect();
+            Path { ops, winding: Winding::NonZero }
+        }
+    }
+
+    impl From<BezPath> for Path {
+        fn from(bez: BezPath) -> Self {
+            (&bez).into()
+        }
+    }
+}
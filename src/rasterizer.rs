@@ -536,12 +536,7 @@ impl Rasterizer {
         while let Some(mut e_ptr) = edge {
             let e = unsafe { e_ptr.as_mut() };
 
-            let inside = match winding_mode {
-                Winding::EvenOdd => winding & 1 != 0,
-                Winding::NonZero => winding != 0,
-            };
-
-            if inside {
+            if winding_mode.is_inside(winding) {
                 blitter.blit_span(
                     self.cur_y,
                     dot16_to_dot2(prevx + (1 << (15 - SAMPLE_SHIFT))),
@@ -550,14 +545,18 @@ impl Rasterizer {
             }
 
             if dot16_to_dot2(e.fullx) >= self.width {
-                break;
+                // we don't need to worry about any edges beyond width
+                return;
             }
             winding += e.winding as i32;
             prevx = e.fullx;
             edge = e.next;
         }
 
-        // we don't need to worry about any edges beyond width
+        // the span after the last edge can only be inside for the inverse winding modes
+        if winding_mode.is_inside(winding) {
+            blitter.blit_span(self.cur_y, dot16_to_dot2(prevx + (1 << (15 - SAMPLE_SHIFT))), self.width);
+        }
     }
 
     // You may have heard that one should never use a bubble sort.
@@ -606,8 +605,12 @@ impl Rasterizer {
     }
 
     pub fn rasterize(&mut self, blitter: &mut dyn RasterBlitter, winding_mode: Winding) {
-        let start = int_to_dot2(self.bounds_top).max(0);
-        let end = int_to_dot2(self.bounds_bottom).min(self.height);
+        // inverse fills cover the rows without any edges too
+        let (start, end) = if winding_mode.is_inverse() {
+            (0, self.height)
+        } else {
+            (int_to_dot2(self.bounds_top).max(0), int_to_dot2(self.bounds_bottom).min(self.height))
+        };
 
         self.cur_y = start;
         while self.cur_y < end {
@@ -0,0 +1,285 @@
+// Polyline simplification and curve fitting for paths with lots of short line segments.
+//
+// Simplification uses the Ramer–Douglas–Peucker algorithm. Curve fitting is based on
+// "An Algorithm for Automatically Fitting Digitized Curves" by Philip J. Schneider
+// from Graphics Gems.
+
+use crate::path_builder::{Path, PathOp};
+use crate::{Point, Vector};
+
+// Directions that change by more than this (the cosine of 60 degrees) are treated
+// as corners when fitting curves. A single cubic can't go around a sharp corner so
+// we split the polyline there instead of spending a lot of segments approximating it.
+const CORNER_COS: f32 = 0.5;
+
+const MAX_REPARAMETERIZE_ITERATIONS: usize = 4;
+
+impl Path {
+    /// Returns a simplified version of `self` where every run of `LineTo` commands
+    /// is reduced with the Ramer–Douglas–Peucker algorithm. No point of the original
+    /// polylines will be further than `tolerance` from the result. Curves are kept as is.
+    pub fn simplify(&self, tolerance: f32) -> Path {
+        map_polylines(self, |points, ops| {
+            let mut keep = vec![false; points.len()];
+            keep[0] = true;
+            keep[points.len() - 1] = true;
+            rdp(points, tolerance, &mut keep);
+            for (pt, keep) in points.iter().zip(keep).skip(1) {
+                if keep {
+                    ops.push(PathOp::LineTo(*pt));
+                }
+            }
+        })
+    }
+
+    /// Returns a version of `self` where every run of `LineTo` commands is replaced
+    /// with cubic beziers that pass within `tolerance` of the original points.
+    /// Sharp corners in the polylines are preserved. Existing curves are kept as is.
+    pub fn fit_curves(&self, tolerance: f32) -> Path {
+        map_polylines(self, |points, ops| {
+            let mut points = points.to_vec();
+            points.dedup();
+            if points.len() < 2 {
+                return;
+            }
+            let mut start = 0;
+            for i in 1..points.len() {
+                if i == points.len() - 1 || is_corner(points[i - 1], points[i], points[i + 1]) {
+                    let piece = &points[start..=i];
+                    let first_tangent = (piece[1] - piece[0]).normalize();
+                    let last_tangent = (piece[piece.len() - 2] - piece[piece.len() - 1]).normalize();
+                    fit_cubic(piece, first_tangent, last_tangent, tolerance, ops);
+                    start = i;
+                }
+            }
+        })
+    }
+}
+
+/// Calls `f` for every run of points connected by line segments in `path`. `f` is
+/// expected to push ops to get from the first point of the run to the last. Closing
+/// segments are made explicit so that they take part in the run they close.
+fn map_polylines(path: &Path, mut f: impl FnMut(&[Point], &mut Vec<PathOp>)) -> Path {
+    let mut ops = Vec::new();
+    for contour in path.contours() {
+        let mut first_point = Point::zero();
+        let mut run: Vec<Point> = Vec::new();
+        for op in &contour.ops {
+            match *op {
+                PathOp::MoveTo(pt) => {
+                    first_point = pt;
+                    run.push(pt);
+                    ops.push(*op);
+                }
+                PathOp::LineTo(pt) => run.push(pt),
+                PathOp::QuadTo(_, pt) | PathOp::CubicTo(_, _, pt) => {
+                    if run.len() > 1 {
+                        f(&run, &mut ops);
+                    }
+                    ops.push(*op);
+                    run.clear();
+                    run.push(pt);
+                }
+                PathOp::Close => {
+                    if run.last() != Some(&first_point) {
+                        run.push(first_point);
+                    }
+                    if run.len() > 1 {
+                        f(&run, &mut ops);
+                    }
+                    ops.push(*op);
+                    run.clear();
+                }
+            }
+        }
+        if run.len() > 1 {
+            f(&run, &mut ops);
+        }
+    }
+    Path { ops, winding: path.winding }
+}
+
+fn distance_to_segment(pt: Point, a: Point, b: Point) -> f32 {
+    let ab = b - a;
+    let len_sq = ab.square_length();
+    if len_sq == 0. {
+        return (pt - a).length();
+    }
+    let t = ((pt - a).dot(ab) / len_sq).clamp(0., 1.);
+    (pt - (a + ab * t)).length()
+}
+
+fn rdp(points: &[Point], tolerance: f32, keep: &mut [bool]) {
+    if points.len() < 3 {
+        return;
+    }
+    let first = points[0];
+    let last = points[points.len() - 1];
+    let mut max_dist = 0.;
+    let mut index = 0;
+    for (i, pt) in points.iter().enumerate().take(points.len() - 1).skip(1) {
+        let dist = distance_to_segment(*pt, first, last);
+        if dist > max_dist {
+            max_dist = dist;
+            index = i;
+        }
+    }
+    if max_dist > tolerance {
+        keep[index] = true;
+        rdp(&points[..=index], tolerance, &mut keep[..=index]);
+        rdp(&points[index..], tolerance, &mut keep[index..]);
+    }
+}
+
+fn is_corner(prev: Point, pt: Point, next: Point) -> bool {
+    let a = (pt - prev).normalize();
+    let b = (next - pt).normalize();
+    a.dot(b) < CORNER_COS
+}
+
+fn bezier_point(c: &[Point; 4], t: f32) -> Point {
+    let mt = 1. - t;
+    let a = mt * mt * mt;
+    let b = 3. * mt * mt * t;
+    let cc = 3. * mt * t * t;
+    let d = t * t * t;
+    Point::new(
+        a * c[0].x + b * c[1].x + cc * c[2].x + d * c[3].x,
+        a * c[0].y + b * c[1].y + cc * c[2].y + d * c[3].y,
+    )
+}
+
+fn fit_cubic(points: &[Point], first_tangent: Vector, last_tangent: Vector, tolerance: f32, ops: &mut Vec<PathOp>) {
+    let first = points[0];
+    let last = points[points.len() - 1];
+    if points.len() == 2 {
+        let dist = (last - first).length() / 3.;
+        ops.push(PathOp::CubicTo(first + first_tangent * dist, last + last_tangent * dist, last));
+        return;
+    }
+
+    let mut u = chord_length_parameterize(points);
+    let mut curve = generate_bezier(points, &u, first_tangent, last_tangent);
+    let (mut max_error, mut split) = compute_max_error(points, &curve, &u);
+    if max_error <= tolerance {
+        ops.push(PathOp::CubicTo(curve[1], curve[2], curve[3]));
+        return;
+    }
+
+    // If the error is not too large try to improve the parameterization
+    if max_error <= tolerance * 4. {
+        for _ in 0..MAX_REPARAMETERIZE_ITERATIONS {
+            u = reparameterize(points, &u, &curve);
+            curve = generate_bezier(points, &u, first_tangent, last_tangent);
+            let (error, index) = compute_max_error(points, &curve, &u);
+            max_error = error;
+            split = index;
+            if max_error <= tolerance {
+                ops.push(PathOp::CubicTo(curve[1], curve[2], curve[3]));
+                return;
+            }
+        }
+    }
+
+    // Fitting failed so split at the point of maximum error and fit each side
+    let mut center_tangent = points[split - 1] - points[split + 1];
+    if center_tangent.square_length() == 0. {
+        center_tangent = (points[split - 1] - points[split]).yx();
+        center_tangent.y = -center_tangent.y;
+    }
+    let center_tangent = center_tangent.normalize();
+    fit_cubic(&points[..=split], first_tangent, center_tangent, tolerance, ops);
+    fit_cubic(&points[split..], -center_tangent, last_tangent, tolerance, ops);
+}
+
+fn chord_length_parameterize(points: &[Point]) -> Vec<f32> {
+    let mut u = Vec::with_capacity(points.len());
+    u.push(0.);
+    for i in 1..points.len() {
+        let prev = u[i - 1];
+        u.push(prev + (points[i] - points[i - 1]).length());
+    }
+    let total = u[u.len() - 1];
+    for t in &mut u {
+        *t /= total;
+    }
+    u
+}
+
+// Uses least squares to find the lengths of the tangent handles
+fn generate_bezier(points: &[Point], u: &[f32], first_tangent: Vector, last_tangent: Vector) -> [Point; 4] {
+    let first = points[0];
+    let last = points[points.len() - 1];
+
+    let mut c = [[0f32; 2]; 2];
+    let mut x = [0f32; 2];
+    for (pt, &t) in points.iter().zip(u) {
+        let mt = 1. - t;
+        let b0 = mt * mt * mt;
+        let b1 = 3. * mt * mt * t;
+        let b2 = 3. * mt * t * t;
+        let b3 = t * t * t;
+        let a0 = first_tangent * b1;
+        let a1 = last_tangent * b2;
+        c[0][0] += a0.dot(a0);
+        c[0][1] += a0.dot(a1);
+        c[1][1] += a1.dot(a1);
+        let tmp = pt.to_vector() - (first.to_vector() * (b0 + b1) + last.to_vector() * (b2 + b3));
+        x[0] += a0.dot(tmp);
+        x[1] += a1.dot(tmp);
+    }
+    c[1][0] = c[0][1];
+
+    let det_c0_c1 = c[0][0] * c[1][1] - c[1][0] * c[0][1];
+    let det_c0_x = c[0][0] * x[1] - c[1][0] * x[0];
+    let det_x_c1 = x[0] * c[1][1] - x[1] * c[0][1];
+    let (alpha_l, alpha_r) = if det_c0_c1 == 0. {
+        (0., 0.)
+    } else {
+        (det_x_c1 / det_c0_c1, det_c0_x / det_c0_c1)
+    };
+
+    // If the alphas are degenerate fall back to the Wu/Barsky heuristic
+    let seg_length = (last - first).length();
+    let epsilon = 1.0e-6 * seg_length;
+    if alpha_l < epsilon || alpha_r < epsilon {
+        let dist = seg_length / 3.;
+        return [first, first + first_tangent * dist, last + last_tangent * dist, last];
+    }
+    [first, first + first_tangent * alpha_l, last + last_tangent * alpha_r, last]
+}
+
+fn compute_max_error(points: &[Point], curve: &[Point; 4], u: &[f32]) -> (f32, usize) {
+    let mut max_dist = 0.;
+    let mut split = points.len() / 2;
+    for i in 1..points.len() - 1 {
+        let dist = (bezier_point(curve, u[i]) - points[i]).length();
+        if dist >= max_dist {
+            max_dist = dist;
+            split = i;
+        }
+    }
+    (max_dist, split)
+}
+
+// Use Newton-Raphson iteration to find better parameter values for each point
+fn reparameterize(points: &[Point], u: &[f32], curve: &[Point; 4]) -> Vec<f32> {
+    let d1 = [
+        (curve[1] - curve[0]) * 3.,
+        (curve[2] - curve[1]) * 3.,
+        (curve[3] - curve[2]) * 3.,
+    ];
+    let d2 = [(d1[1] - d1[0]) * 2., (d1[2] - d1[1]) * 2.];
+    points.iter().zip(u).map(|(pt, &t)| {
+        let mt = 1. - t;
+        let diff = bezier_point(curve, t) - *pt;
+        let first = d1[0] * (mt * mt) + d1[1] * (2. * mt * t) + d1[2] * (t * t);
+        let second = d2[0] * mt + d2[1] * t;
+        let denominator = first.dot(first) + diff.dot(second);
+        if denominator == 0. {
+            t
+        } else {
+            (t - diff.dot(first) / denominator).clamp(0., 1.)
+        }
+    }).collect()
+}
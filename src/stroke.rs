@@ -1,8 +1,14 @@
-// This is a simple path stroker. It flattens the path and strokes each segment individually.
+// This is a simple path stroker. It strokes each segment of the path individually. Lines become
+// quads and curves are offset on both sides with cubics, falling back to flattening when that
+// doesn't work well.
 // For a recent survey of stroking approaches see "Converting stroked primitives to filled primitives" by Diego Nehab
 
-use crate::path_builder::{Path, PathBuilder, PathOp};
-use crate::{Point, Vector};
+use crate::dash::dash_path_with_tolerance;
+use crate::draw_target::scaled_tolerance;
+use crate::path_builder::{Path, PathBuilder, PathOp, Winding};
+use crate::{Point, Rect, Transform, Vector};
+
+use lyon_geom::{CubicBezierSegment, QuadraticBezierSegment};
 
 #[derive(Clone, PartialEq, Debug)]
 pub struct StrokeStyle {
@@ -32,6 +38,8 @@ pub enum LineCap {
     Round,
     Square,
     Butt,
+    /// A triangle that extends half the stroke width beyond the end of the line
+    Triangle,
 }
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -39,6 +47,15 @@ pub enum LineJoin {
     Round,
     Miter,
     Bevel,
+    /// Like `Miter` but when the miter limit is exceeded the miter is clipped at
+    /// `miter_limit * width / 2` from the join instead of falling back to a bevel.
+    /// This is SVG 2's `miter-clip`.
+    MiterClip,
+    /// SVG 2's `arcs` join. The outer edges of the stroke are extended with the circles
+    /// matching the curvature of the segments at the join until they meet. Straight
+    /// segments are extended with lines. If the extensions don't meet within the miter
+    /// limit this behaves like `MiterClip`.
+    Arcs,
 }
 
 fn compute_normal(p0: Point, p1: Point) -> Option<Vector> {
@@ -170,9 +187,27 @@ fn cap_line(dest: &mut PathBuilder, style: &StrokeStyle, pt: Point, normal: Vect
             dest.line_to(pt.x, pt.y);
             dest.close();
         }
+        LineCap::Triangle => {
+            // parallel vector
+            let v = Vector::new(normal.y, -normal.x);
+            let tip = pt + v * offset;
+            dest.move_to(pt.x + normal.x * offset, pt.y + normal.y * offset);
+            dest.line_to(tip.x, tip.y);
+            dest.line_to(pt.x - normal.x * offset, pt.y - normal.y * offset);
+            dest.line_to(pt.x, pt.y);
+            dest.close();
+        }
     }
 }
 
+// Subpaths of zero length have no direction so, like SVG, we draw their caps aligned with the x axis.
+// This is what makes zero length dashes show up as dots.
+fn cap_point(dest: &mut PathBuilder, style: &StrokeStyle, pt: Point) {
+    let normal = Vector::new(0., 1.);
+    cap_line(dest, style, pt, normal);
+    cap_line(dest, style, pt, flip(normal));
+}
+
 fn bevel(
     dest: &mut PathBuilder,
     style: &StrokeStyle,
@@ -238,15 +273,19 @@ fn join_line(
     pt: Point,
     mut s1_normal: Vector,
     mut s2_normal: Vector,
+    mut curvatures: (f32, f32),
 ) {
     if is_interior_angle(s1_normal, s2_normal) {
         s2_normal = flip(s2_normal);
         s1_normal = flip(s1_normal);
         std::mem::swap(&mut s1_normal, &mut s2_normal);
+        // the curvatures are relative to the normals
+        curvatures = (-curvatures.1, -curvatures.0);
     }
 
-    // XXX: joining uses `pt` which can cause seams because it lies halfway on a line and the
-    // rasterizer may not find exactly the same spot
+    // Joining uses `pt` which lies halfway on the end of the segments. The segments include
+    // `pt` as a point on their edge (see stroke_line) so that the rasterizer sees exactly the
+    // same end points for the edges of the join and of the segment and doesn't leave seams.
     let offset = style.width / 2.;
     match style.join {
         LineJoin::Round => {
@@ -256,18 +295,8 @@ fn join_line(
             dest.close();
         }
         LineJoin::Miter => {
-            let in_dot_out = -s1_normal.x * s2_normal.x + -s1_normal.y * s2_normal.y;
-            if 2. <= style.miter_limit * style.miter_limit * (1. - in_dot_out) {
-                let start = pt + s1_normal * offset;
-                let end = pt + s2_normal * offset;
-                if let Some(intersection) = line_intersection(start, s1_normal, end, s2_normal) {
-                    // We won't have an intersection if the segments are parallel
-                    dest.move_to(pt.x + s1_normal.x * offset, pt.y + s1_normal.y * offset);
-                    dest.line_to(intersection.x, intersection.y);
-                    dest.line_to(pt.x + s2_normal.x * offset, pt.y + s2_normal.y * offset);
-                    dest.line_to(pt.x, pt.y);
-                    dest.close();
-                }
+            if within_miter_limit(style, s1_normal, s2_normal) {
+                miter(dest, pt, s1_normal, s2_normal, offset);
             } else {
                 bevel(dest, style, pt, s1_normal, s2_normal);
             }
@@ -275,10 +304,413 @@ fn join_line(
         LineJoin::Bevel => {
             bevel(dest, style, pt, s1_normal, s2_normal);
         }
+        LineJoin::MiterClip => {
+            miter_clip(dest, style, pt, s1_normal, s2_normal);
+        }
+        LineJoin::Arcs => {
+            join_arcs(dest, style, pt, (s1_normal, s2_normal), curvatures);
+        }
+    }
+}
+
+fn within_miter_limit(style: &StrokeStyle, s1_normal: Vector, s2_normal: Vector) -> bool {
+    let in_dot_out = -s1_normal.x * s2_normal.x + -s1_normal.y * s2_normal.y;
+    2. <= style.miter_limit * style.miter_limit * (1. - in_dot_out)
+}
+
+fn miter(dest: &mut PathBuilder, pt: Point, s1_normal: Vector, s2_normal: Vector, offset: f32) {
+    let start = pt + s1_normal * offset;
+    let end = pt + s2_normal * offset;
+    if let Some(intersection) = line_intersection(start, s1_normal, end, s2_normal) {
+        // We won't have an intersection if the segments are parallel
+        dest.move_to(pt.x + s1_normal.x * offset, pt.y + s1_normal.y * offset);
+        dest.line_to(intersection.x, intersection.y);
+        dest.line_to(pt.x + s2_normal.x * offset, pt.y + s2_normal.y * offset);
+        dest.line_to(pt.x, pt.y);
+        dest.close();
+    }
+}
+
+/* Returns the direction that bisects the outside of the join. If the path turns all the
+ * way around this is the direction of the first segment. */
+fn join_direction(s1_normal: Vector, s2_normal: Vector) -> Vector {
+    let mid = s1_normal + s2_normal;
+    if mid == Vector::zero() {
+        unperp(s1_normal)
+    } else {
+        mid.normalize()
+    }
+}
+
+/* Returns the points where the outer edges of the segments cross the line that clips
+ * the miter or None if the bevel already reaches past that line. `mid` is the direction
+ * that bisects the outside of the join. */
+fn miter_clip_points(style: &StrokeStyle, pt: Point, s1_normal: Vector, s2_normal: Vector, mid: Vector) -> Option<(Point, Point)> {
+    let offset = style.width / 2.;
+    let clip = style.miter_limit * offset;
+    let clip_point = |normal: Vector| {
+        let start = pt + normal * offset;
+        let distance = dot(normal, mid) * offset;
+        if distance >= clip {
+            return None;
+        }
+        // the direction along the edge towards the tip of the miter
+        let mut parallel = unperp(normal);
+        if dot(parallel, mid) < 0. {
+            parallel = flip(parallel);
+        }
+        Some(start + parallel * ((clip - distance) / dot(parallel, mid)))
+    };
+    Some((clip_point(s1_normal)?, clip_point(s2_normal)?))
+}
+
+fn miter_clip(dest: &mut PathBuilder, style: &StrokeStyle, pt: Point, s1_normal: Vector, s2_normal: Vector) {
+    let offset = style.width / 2.;
+    if within_miter_limit(style, s1_normal, s2_normal) {
+        miter(dest, pt, s1_normal, s2_normal, offset);
+    } else if let Some((p1, p2)) = miter_clip_points(style, pt, s1_normal, s2_normal, join_direction(s1_normal, s2_normal)) {
+        dest.move_to(pt.x + s1_normal.x * offset, pt.y + s1_normal.y * offset);
+        dest.line_to(p1.x, p1.y);
+        dest.line_to(p2.x, p2.y);
+        dest.line_to(pt.x + s2_normal.x * offset, pt.y + s2_normal.y * offset);
+        dest.line_to(pt.x, pt.y);
+        dest.close();
+    } else {
+        bevel(dest, style, pt, s1_normal, s2_normal);
+    }
+}
+
+// Curvatures with a radius much larger than the stroke are treated as straight lines
+const MIN_ARCS_CURVATURE: f32 = 1e-3;
+
+/* The outer edge of a segment at a join extended towards the tip of the join. This
+ * is either a line or the offset of the circle of curvature of the segment. */
+struct JoinSide {
+    start: Point,
+    // the direction towards the tip of the join
+    direction: Vector,
+    circle: Option<(Point, f32)>,
+}
+
+impl JoinSide {
+    fn new(pt: Point, normal: Vector, curvature: f32, offset: f32, mid: Vector) -> JoinSide {
+        let start = pt + normal * offset;
+        let mut direction = unperp(normal);
+        if dot(direction, mid) < 0. {
+            direction = flip(direction);
+        }
+        let circle = if curvature.is_finite() && (curvature * offset).abs() > MIN_ARCS_CURVATURE {
+            let center = pt + normal / curvature;
+            Some((center, (start - center).length()))
+        } else {
+            None
+        };
+        JoinSide { start, direction, circle }
+    }
+
+    // Whether going from `start` towards `direction` reaches `pt` before going half way around
+    fn heads_to(&self, pt: Point) -> bool {
+        match self.circle {
+            None => dot(pt - self.start, self.direction) > 0.,
+            Some((center, _)) => {
+                let a = self.start - center;
+                let b = pt - center;
+                let turn = a.cross(b);
+                let tangent = if turn > 0. { perp(a) } else { flip(perp(a)) };
+                turn != 0. && dot(tangent, self.direction) > 0.
+            }
+        }
+    }
+
+    fn intersections(&self, other: &JoinSide) -> [Option<Point>; 2] {
+        match (self.circle, other.circle) {
+            (None, None) => [line_intersection(self.start, perp(self.direction), other.start, perp(other.direction)), None],
+            (None, Some(circle)) => line_circle_intersections(self.start, self.direction, circle),
+            (Some(circle), None) => line_circle_intersections(other.start, other.direction, circle),
+            (Some((c1, r1)), Some((c2, r2))) => {
+                let d = c2 - c1;
+                let distance = d.length();
+                if distance == 0. || distance > r1 + r2 || distance < (r1 - r2).abs() {
+                    return [None, None];
+                }
+                let a = (r1 * r1 - r2 * r2 + distance * distance) / (2. * distance);
+                let h = (r1 * r1 - a * a).max(0.).sqrt();
+                let base = c1 + d * (a / distance);
+                let across = perp(d / distance) * h;
+                [Some(base + across), Some(base - across)]
+            }
+        }
+    }
+
+    // Adds the part of the extension between `from` and `to`
+    fn add_to(&self, dest: &mut PathBuilder, from: Point, to: Point) {
+        match self.circle {
+            None => dest.line_to(to.x, to.y),
+            Some((center, radius)) => {
+                let a = (from - center) / radius;
+                let b = (to - center) / radius;
+                let mid = (a + b).normalize();
+                arc_segment(dest, center.x, center.y, radius, a, mid);
+                arc_segment(dest, center.x, center.y, radius, mid, b);
+            }
+        }
+    }
+}
+
+fn line_circle_intersections(start: Point, direction: Vector, (center, radius): (Point, f32)) -> [Option<Point>; 2] {
+    // solve |start + direction * t - center| = radius for t
+    let v = start - center;
+    let b = dot(direction, v);
+    let c = dot(v, v) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0. {
+        return [None, None];
+    }
+    let root = discriminant.sqrt();
+    [Some(start + direction * (-b + root)), Some(start + direction * (-b - root))]
+}
+
+fn join_arcs(dest: &mut PathBuilder, style: &StrokeStyle, pt: Point, (s1_normal, s2_normal): (Vector, Vector), curvatures: (f32, f32)) {
+    let offset = style.width / 2.;
+    let mid = join_direction(s1_normal, s2_normal);
+    let side1 = JoinSide::new(pt, s1_normal, curvatures.0, offset, mid);
+    let side2 = JoinSide::new(pt, s2_normal, curvatures.1, offset, mid);
+    if side1.circle.is_none() && side2.circle.is_none() {
+        // without curvature this is just a miter
+        miter_clip(dest, style, pt, s1_normal, s2_normal);
+        return;
+    }
+
+    // use the closest point where the extensions meet
+    let limit = style.miter_limit * offset;
+    let tip = side1.intersections(&side2).iter()
+        .flatten()
+        .filter(|&&x| side1.heads_to(x) && side2.heads_to(x) && (x - pt).length() <= limit)
+        .fold(None, |closest: Option<Point>, &x| match closest {
+            Some(closest) if (closest - pt).length() <= (x - pt).length() => Some(closest),
+            _ => Some(x),
+        });
+    match tip {
+        Some(tip) => {
+            dest.move_to(side1.start.x, side1.start.y);
+            side1.add_to(dest, side1.start, tip);
+            side2.add_to(dest, tip, side2.start);
+            dest.line_to(pt.x, pt.y);
+            dest.close();
+        }
+        None => miter_clip(dest, style, pt, s1_normal, s2_normal),
     }
 }
 
+// Curves are subdivided at most this many times before they are flattened instead
+const MAX_CURVE_SUBDIVISIONS: u32 = 8;
+
+// Parts of curves that are too tight to be offset are subdivided at most this many times
+const MAX_TIGHT_CURVE_SUBDIVISIONS: u32 = 3;
+
+// Curve pieces that turn more than this (the cosine of 60 degrees) are split before offsetting
+const MAX_CURVE_TURN_COS: f32 = 0.5;
+
+// The tolerance `stroke_to_path` uses when approximating the offsets of curves
+const CURVE_TOLERANCE: f32 = 0.1;
+
+/// Dashes `path` if `style` has a dash array and strokes the result, approximating
+/// curves within `tolerance`. This is the same sequence of operations that `DrawTarget::stroke` uses.
+pub(crate) fn dash_and_stroke(path: &Path, style: &StrokeStyle, tolerance: f32) -> Path {
+    dash_and_stroke_with_length(path, style, None, tolerance)
+}
+
+/// Like `dash_and_stroke` but the dashes are relative to `path_length` instead of the
+/// actual length of the path when it is given
+pub(crate) fn dash_and_stroke_with_length(path: &Path, style: &StrokeStyle, path_length: Option<f32>, tolerance: f32) -> Path {
+    if !style.dash_array.is_empty() {
+        let path = dash_path_with_tolerance(path, &style.dash_array, style.dash_offset, path_length, tolerance);
+        return stroke_to_path_with_tolerance(&path, style, tolerance);
+    }
+    stroke_to_path_with_tolerance(path, style, tolerance)
+}
+
+/// Dashes and strokes `path` with `style` the same way `DrawTarget::stroke` does and returns the
+/// area covered by the stroke along with conservative bounds for it. Both are transformed by
+/// `transform` so the result can be drawn with `DrawTarget::fill` using the identity transform.
+/// Curves are approximated within `tolerance` after the transform is applied.
+pub fn stroke_path(path: &Path, style: &StrokeStyle, tolerance: f32, transform: Option<&Transform>) -> (Path, Option<Rect>) {
+    let transform = transform.copied().unwrap_or_default();
+    let stroked = dash_and_stroke(path, style, scaled_tolerance(tolerance, &transform));
+    (stroked.transform(&transform), path.stroke_bounds(style, &transform))
+}
+
+fn stroke_line(dest: &mut PathBuilder, from: Point, to: Point, normal: Vector, half_width: f32) {
+    stroke_tapered_line(dest, (from, half_width), (to, half_width), normal);
+}
+
+// Like stroke_line but the half width can be different at each end
+fn stroke_tapered_line(dest: &mut PathBuilder, (from, from_width): (Point, f32), (to, to_width): (Point, f32), normal: Vector) {
+    dest.move_to(from.x + normal.x * from_width, from.y + normal.y * from_width);
+    dest.line_to(to.x + normal.x * to_width, to.y + normal.y * to_width);
+    // we add a point at the midpoint of the line so that our edge has matching
+    // end points with the edges used for joining. This avoids seams during
+    // rasterization caused by precision differences in the slope and endpoints
+    dest.line_to(to.x, to.y);
+    dest.line_to(to.x + -normal.x * to_width, to.y + -normal.y * to_width);
+    dest.line_to(from.x - normal.x * from_width, from.y - normal.y * from_width);
+    dest.line_to(from.x, from.y);
+    dest.close();
+}
+
+/// Returns the normals at the start and end of `c` or None if all of its points are the same.
+fn cubic_normals(c: &CubicBezierSegment<f32>) -> Option<(Vector, Vector)> {
+    let start = compute_normal(c.from, c.ctrl1)
+        .or_else(|| compute_normal(c.from, c.ctrl2))
+        .or_else(|| compute_normal(c.from, c.to))?;
+    let end = compute_normal(c.ctrl2, c.to)
+        .or_else(|| compute_normal(c.ctrl1, c.to))
+        .or_else(|| compute_normal(c.from, c.to))?;
+    Some((start, end))
+}
+
+/// The signed curvature of `c` at `t`. It's positive when the curve turns towards its normal.
+fn curvature(c: &CubicBezierSegment<f32>, t: f32) -> f32 {
+    let d1 = c.derivative(t);
+    let d2 = ((c.ctrl2 - c.ctrl1 * 2. + c.from.to_vector()) * (1. - t)
+        + (c.to - c.ctrl2 * 2. + c.ctrl1.to_vector()) * t)
+        * 6.;
+    let len = d1.length();
+    if len == 0. {
+        return f32::INFINITY;
+    }
+    d1.cross(d2) / (len * len * len)
+}
+
+/// Approximates the curve at `distance` along the normals of `c` by moving the end points
+/// along their normals and scaling the handles by the change in the radius of curvature.
+fn offset_cubic(c: &CubicBezierSegment<f32>, normals: (Vector, Vector), distance: f32) -> CubicBezierSegment<f32> {
+    let from = c.from + normals.0 * distance;
+    let to = c.to + normals.1 * distance;
+    let start_scale = (1. - distance * curvature(c, 0.)).max(0.);
+    let end_scale = (1. - distance * curvature(c, 1.)).max(0.);
+    CubicBezierSegment {
+        from,
+        ctrl1: from + (c.ctrl1 - c.from) * start_scale,
+        ctrl2: to + (c.ctrl2 - c.to) * end_scale,
+        to,
+    }
+}
+
+fn offset_error(c: &CubicBezierSegment<f32>, offset: &CubicBezierSegment<f32>, distance: f32) -> f32 {
+    let mut error: f32 = 0.;
+    for &t in &[0.25, 0.5, 0.75] {
+        let derivative = c.derivative(t);
+        if derivative.square_length() == 0. {
+            continue;
+        }
+        let expected = c.sample(t) + perp(derivative.normalize()) * distance;
+        error = error.max((offset.sample(t) - expected).length());
+    }
+    error
+}
+
+struct CurveStroker<'a> {
+    dest: &'a mut PathBuilder,
+    half_width: f32,
+    tolerance: f32,
+    first_normal: Option<Vector>,
+    last_normal: Option<Vector>,
+}
+
+impl<'a> CurveStroker<'a> {
+    // The pieces of a curve are joined with round joins. They are only needed when the
+    // direction of the curve changes abruptly, like at cusps or between flattened lines.
+    fn join(&mut self, pt: Point, normal: Vector) {
+        if let Some(last_normal) = self.last_normal {
+            if dot(last_normal, normal) > 0. && last_normal.cross(normal).abs() * self.half_width < self.tolerance / 10. {
+                return;
+            }
+            let (mut s1_normal, mut s2_normal) = (last_normal, normal);
+            if is_interior_angle(s1_normal, s2_normal) {
+                s1_normal = flip(normal);
+                s2_normal = flip(last_normal);
+            }
+            self.dest.move_to(pt.x + s1_normal.x * self.half_width, pt.y + s1_normal.y * self.half_width);
+            join_round(self.dest, pt, s1_normal, s2_normal, self.half_width);
+            self.dest.line_to(pt.x, pt.y);
+            self.dest.close();
+        }
+    }
+
+    fn flatten(&mut self, c: &CubicBezierSegment<f32>) {
+        c.for_each_flattened(self.tolerance, &mut |line| {
+            if let Some(normal) = compute_normal(line.from, line.to) {
+                self.join(line.from, normal);
+                stroke_line(self.dest, line.from, line.to, normal, self.half_width);
+                self.first_normal = self.first_normal.or(Some(normal));
+                self.last_normal = Some(normal);
+            }
+        });
+    }
+
+    fn split(&mut self, c: &CubicBezierSegment<f32>, depth: u32) {
+        let (first, second) = c.split(0.5);
+        self.stroke(&first, depth + 1);
+        self.stroke(&second, depth + 1);
+    }
+
+    fn stroke(&mut self, c: &CubicBezierSegment<f32>, depth: u32) {
+        let normals = match cubic_normals(c) {
+            Some(normals) => normals,
+            None => return,
+        };
+
+        // When the radius of curvature is smaller than the half width the offset on the
+        // inside of the curve turns around. The region between the offsets would then have
+        // parts with the opposite winding so we fall back to stroking the flattened curve.
+        // We split a few times first so that only the tight parts of the curve are flattened.
+        let tight = [0., 0.5, 1.].iter().any(|&t| curvature(c, t).abs() * self.half_width >= 1.);
+        if tight && depth < MAX_TIGHT_CURVE_SUBDIVISIONS {
+            self.split(c, depth);
+            return;
+        }
+        if tight || depth == MAX_CURVE_SUBDIVISIONS {
+            self.flatten(c);
+            return;
+        }
+
+        if dot(normals.0, normals.1) < MAX_CURVE_TURN_COS {
+            self.split(c, depth);
+            return;
+        }
+
+        let left = offset_cubic(c, normals, self.half_width);
+        let right = offset_cubic(c, normals, -self.half_width);
+        if offset_error(c, &left, self.half_width) > self.tolerance
+            || offset_error(c, &right, -self.half_width) > self.tolerance
+        {
+            self.split(c, depth);
+            return;
+        }
+
+        self.join(c.from, normals.0);
+        // like with lines we include the points on the curve to avoid seams
+        self.dest.move_to(left.from.x, left.from.y);
+        self.dest.cubic_to(left.ctrl1.x, left.ctrl1.y, left.ctrl2.x, left.ctrl2.y, left.to.x, left.to.y);
+        self.dest.line_to(c.to.x, c.to.y);
+        self.dest.line_to(right.to.x, right.to.y);
+        self.dest.cubic_to(right.ctrl2.x, right.ctrl2.y, right.ctrl1.x, right.ctrl1.y, right.from.x, right.from.y);
+        self.dest.line_to(c.from.x, c.from.y);
+        self.dest.close();
+        self.first_normal = self.first_normal.or(Some(normals.0));
+        self.last_normal = Some(normals.1);
+    }
+}
+
+/// Strokes `path` using `style` and returns the area covered by the stroke. The result is made
+/// of overlapping pieces so it needs to be filled with the nonzero winding rule. Curves are offset
+/// directly instead of being flattened first.
 pub fn stroke_to_path(path: &Path, style: &StrokeStyle) -> Path {
+    stroke_to_path_with_tolerance(path, style, CURVE_TOLERANCE)
+}
+
+pub(crate) fn stroke_to_path_with_tolerance(path: &Path, style: &StrokeStyle, tolerance: f32) -> Path {
     let mut stroked_path = PathBuilder::new();
 
     if style.width <= 0. {
@@ -287,18 +719,25 @@ pub fn stroke_to_path(path: &Path, style: &StrokeStyle) -> Path {
 
     let mut cur_pt = None;
     let mut last_normal = Vector::zero();
+    // the curvature at the end of the last segment which is used by `LineJoin::Arcs`
+    let mut last_curvature = 0.;
     let half_width = style.width / 2.;
     let mut start_point = None;
+    // whether the current subpath only has segments of zero length
+    let mut zero_length = false;
     for op in &path.ops {
         match *op {
             PathOp::MoveTo(pt) => {
-                if let (Some(cur_pt), Some((point, normal))) = (cur_pt, start_point) {
+                if let (Some(cur_pt), Some((point, normal, _))) = (cur_pt, start_point) {
                     // cap end
                     cap_line(&mut stroked_path, style, cur_pt, last_normal);
                     // cap beginning
                     cap_line(&mut stroked_path, style, point, flip(normal));
+                } else if let (Some(cur_pt), true) = (cur_pt, zero_length) {
+                    cap_point(&mut stroked_path, style, cur_pt);
                 }
                 start_point = None;
+                zero_length = false;
                 cur_pt = Some(pt);
             }
             PathOp::LineTo(pt) => {
@@ -307,85 +746,593 @@ pub fn stroke_to_path(path: &Path, style: &StrokeStyle) -> Path {
                 } else if let Some(cur_pt) = cur_pt {
                     if let Some(normal) = compute_normal(cur_pt, pt) {
                         if start_point.is_none() {
-                            start_point = Some((cur_pt, normal));
+                            start_point = Some((cur_pt, normal, 0.));
                         } else {
-                            join_line(&mut stroked_path, style, cur_pt, last_normal, normal);
+                            join_line(&mut stroked_path, style, cur_pt, last_normal, normal, (last_curvature, 0.));
                         }
 
-                        stroked_path.move_to(
-                            cur_pt.x + normal.x * half_width,
-                            cur_pt.y + normal.y * half_width,
-                        );
-                        stroked_path.line_to(pt.x + normal.x * half_width, pt.y + normal.y * half_width);
-                        // we add a point at the midpoint of the line so that our edge has matching
-                        // end points with the edges used for joining. This avoids seams during
-                        // rasterization caused by precision differences in the slope and endpoints
-                        stroked_path.line_to(pt.x, pt.y);
-                        stroked_path.line_to(pt.x + -normal.x * half_width, pt.y + -normal.y * half_width);
-                        stroked_path.line_to(
-                            cur_pt.x - normal.x * half_width,
-                            cur_pt.y - normal.y * half_width,
-                        );
-                        stroked_path.line_to(cur_pt.x, cur_pt.y);
-
-                        stroked_path.close();
+                        stroke_line(&mut stroked_path, cur_pt, pt, normal, half_width);
 
                         last_normal = normal;
+                        last_curvature = 0.;
 
+                    } else if start_point.is_none() {
+                        zero_length = true;
                     }
                 }
                 cur_pt = Some(pt);
 
             }
+            PathOp::QuadTo(..) | PathOp::CubicTo(..) => {
+                // like DrawTarget, a curve without a current point starts at its first control point
+                let curve = match *op {
+                    PathOp::QuadTo(ctrl, to) => {
+                        QuadraticBezierSegment { from: cur_pt.unwrap_or(ctrl), ctrl, to }.to_cubic()
+                    }
+                    PathOp::CubicTo(ctrl1, ctrl2, to) => {
+                        CubicBezierSegment { from: cur_pt.unwrap_or(ctrl1), ctrl1, ctrl2, to }
+                    }
+                    _ => unreachable!(),
+                };
+                let mut stroker = CurveStroker {
+                    dest: &mut stroked_path,
+                    half_width,
+                    tolerance,
+                    first_normal: None,
+                    last_normal: None,
+                };
+                stroker.stroke(&curve, 0);
+                // the curve may have been flattened so we use the normals of the pieces
+                // that were actually stroked for joining and capping
+                if let (Some(normal), Some(end_normal)) = (stroker.first_normal, stroker.last_normal) {
+                    let start_curvature = curvature(&curve, 0.);
+                    if start_point.is_none() {
+                        start_point = Some((curve.from, normal, start_curvature));
+                    } else {
+                        join_line(&mut stroked_path, style, curve.from, last_normal, normal, (last_curvature, start_curvature));
+                    }
+                    last_normal = end_normal;
+                    last_curvature = curvature(&curve, 1.);
+                } else if start_point.is_none() {
+                    zero_length = true;
+                }
+                cur_pt = Some(curve.to);
+            }
             PathOp::Close => {
-                if let (Some(cur_pt), Some((end_point, start_normal))) = (cur_pt, start_point) {
+                if let (Some(cur_pt), Some((end_point, start_normal, start_curvature))) = (cur_pt, start_point) {
                     if let Some(normal) = compute_normal(cur_pt, end_point) {
-                        join_line(&mut stroked_path, style, cur_pt, last_normal, normal);
+                        join_line(&mut stroked_path, style, cur_pt, last_normal, normal, (last_curvature, 0.));
 
                         // the closing line segment
-                        stroked_path.move_to(
-                            cur_pt.x + normal.x * half_width,
-                            cur_pt.y + normal.y * half_width,
-                        );
-                        stroked_path.line_to(
-                            end_point.x + normal.x * half_width,
-                            end_point.y + normal.y * half_width,
-                        );
-                        stroked_path.line_to(
-                            end_point.x,
-                            end_point.y,
-                        );
-                        stroked_path.line_to(
-                            end_point.x + -normal.x * half_width,
-                            end_point.y + -normal.y * half_width,
-                        );
-                        stroked_path.line_to(
-                            cur_pt.x - normal.x * half_width,
-                            cur_pt.y - normal.y * half_width,
-                        );
-                        stroked_path.line_to(
-                            cur_pt.x,
-                            cur_pt.y,
-                        );
-                        stroked_path.close();
-
-                        join_line(&mut stroked_path, style, end_point, normal, start_normal);
+                        stroke_line(&mut stroked_path, cur_pt, end_point, normal, half_width);
+
+                        join_line(&mut stroked_path, style, end_point, normal, start_normal, (0., start_curvature));
                     } else {
-                        join_line(&mut stroked_path, style, end_point, last_normal, start_normal);
+                        join_line(&mut stroked_path, style, end_point, last_normal, start_normal, (last_curvature, start_curvature));
                     }
+                } else if let (Some(cur_pt), true) = (cur_pt, zero_length) {
+                    cap_point(&mut stroked_path, style, cur_pt);
                 }
                 cur_pt = start_point.map(|x| x.0);
                 start_point = None;
+                zero_length = false;
             }
-            PathOp::QuadTo(..) => panic!("Only flat paths handled"),
-            PathOp::CubicTo(..) => panic!("Only flat paths handled"),
         }
     }
-    if let (Some(cur_pt), Some((point, normal))) = (cur_pt, start_point) {
+    if let (Some(cur_pt), Some((point, normal, _))) = (cur_pt, start_point) {
         // cap end
         cap_line(&mut stroked_path, style, cur_pt, last_normal);
         // cap beginning
         cap_line(&mut stroked_path, style, point, flip(normal));
+    } else if let (Some(cur_pt), true) = (cur_pt, zero_length) {
+        cap_point(&mut stroked_path, style, cur_pt);
+    }
+    stroked_path.finish()
+}
+
+fn outline_cap(dest: &mut PathBuilder, style: &StrokeStyle, pt: Point, normal: Vector) {
+    // the current point is pt + normal * offset and we need to end up at pt - normal * offset
+    let offset = style.width / 2.;
+    match style.cap {
+        LineCap::Butt => {}
+        LineCap::Round => {
+            arc(dest, pt.x, pt.y, offset, normal, flip(normal));
+        }
+        LineCap::Square => {
+            // parallel vector
+            let v = Vector::new(normal.y, -normal.x);
+            let end = pt + v * offset;
+            dest.line_to(end.x + normal.x * offset, end.y + normal.y * offset);
+            dest.line_to(end.x - normal.x * offset, end.y - normal.y * offset);
+        }
+        LineCap::Triangle => {
+            let tip = pt + Vector::new(normal.y, -normal.x) * offset;
+            dest.line_to(tip.x, tip.y);
+        }
+    }
+    dest.line_to(pt.x - normal.x * offset, pt.y - normal.y * offset);
+}
+
+// Adds the join on the outside of a corner. The current point is pt + s1_normal * offset
+// and we end up at pt + s2_normal * offset. `direction` is the direction of the first segment.
+fn outline_outer_join(dest: &mut PathBuilder, style: &StrokeStyle, pt: Point, s1_normal: Vector, s2_normal: Vector, direction: Vector) {
+    let offset = style.width / 2.;
+    let end = pt + s2_normal * offset;
+    match style.join {
+        LineJoin::Round => {
+            // Unlike `arc` we need to go in either direction around the center. The
+            // outside of the corner is always the smaller angle between the normals
+            // except when the path turns all the way around.
+            let mut mid = s1_normal + s2_normal;
+            if mid == Vector::zero() {
+                mid = direction;
+            }
+            let mid = mid.normalize();
+            arc_segment(dest, pt.x, pt.y, offset, s1_normal, mid);
+            arc_segment(dest, pt.x, pt.y, offset, mid, s2_normal);
+        }
+        LineJoin::Miter | LineJoin::MiterClip | LineJoin::Arcs => {
+            let start = pt + s1_normal * offset;
+            if within_miter_limit(style, s1_normal, s2_normal) {
+                if let Some(intersection) = line_intersection(start, s1_normal, end, s2_normal) {
+                    dest.line_to(intersection.x, intersection.y);
+                }
+            } else if style.join != LineJoin::Miter {
+                // the outline only has straight segments so arcs are the same as miter-clip
+                let mut mid = s1_normal + s2_normal;
+                if mid == Vector::zero() {
+                    mid = direction;
+                }
+                if let Some((p1, p2)) = miter_clip_points(style, pt, s1_normal, s2_normal, mid.normalize()) {
+                    dest.line_to(p1.x, p1.y);
+                    dest.line_to(p2.x, p2.y);
+                }
+            }
+        }
+        LineJoin::Bevel => {}
+    }
+    dest.line_to(end.x, end.y);
+}
+
+// The portion of a segment that is still available on one side of the outline
+// expressed as parameters along the segment's center line.
+#[derive(Clone, Copy)]
+struct SideRange {
+    start: f32,
+    end: f32,
+}
+
+// Adds the join on the inside of a corner. Where possible the two offset lines are trimmed
+// to their intersection so that they don't overlap. If the intersection doesn't lie on
+// both segments (i.e. the segments are shorter than the stroke is wide) we connect through
+// `pt` like `stroke_to_path` does and `remove_inner_loops` trims the lines against the ones
+// before and after them later. Returns where the next segment starts.
+fn outline_inner_join(
+    dest: &mut PathBuilder,
+    offset: f32,
+    seg1: (Point, Point),
+    seg2: (Point, Point),
+    (s1_normal, s2_normal): (Vector, Vector),
+    range1: SideRange,
+    next_end: f32,
+) -> f32 {
+    let pt = seg1.1;
+    let start = pt + s1_normal * offset;
+    let end = pt + s2_normal * offset;
+    if let Some(intersection) = line_intersection(start, s1_normal, end, s2_normal) {
+        let d1 = seg1.1 - seg1.0;
+        let d2 = seg2.1 - seg2.0;
+        let t1 = dot(intersection - seg1.0, d1) / dot(d1, d1);
+        let t2 = dot(intersection - seg2.0, d2) / dot(d2, d2);
+        if t1 >= range1.start && t1 <= range1.end && t2 >= 0. && t2 <= next_end {
+            dest.line_to(intersection.x, intersection.y);
+            return t2;
+        }
+    }
+    dest.line_to(start.x, start.y);
+    dest.line_to(pt.x, pt.y);
+    dest.line_to(end.x, end.y);
+    0.
+}
+
+// Returns where the segments `a` to `b` and `c` to `d` cross, excluding the start of the first
+fn segment_intersection(a: Point, b: Point, c: Point, d: Point) -> Option<Point> {
+    let (r, s) = (b - a, d - c);
+    let denom = r.cross(s);
+    if denom == 0. {
+        return None;
+    }
+    let t = (c - a).cross(s) / denom;
+    let u = (c - a).cross(r) / denom;
+    if t > 0. && t <= 1. && (0. ..=1.).contains(&u) {
+        Some(a + r * t)
+    } else {
+        None
+    }
+}
+
+// Removes the loops that the inner joins of segments shorter than the stroke is wide leave in
+// a side of the outline. When a line of the side crosses a later line that is within `reach`
+// of it, everything between them is inside of the stroke and is replaced by the crossing.
+// Only nearby lines are checked so that sides that cross themselves because the path does are
+// kept.
+fn remove_inner_loops(ops: &mut Vec<PathOp>, reach: f32) {
+    let mut i = 1;
+    while i < ops.len() {
+        if let PathOp::LineTo(b) = ops[i] {
+            let a = op_end(&ops[i - 1]).unwrap();
+            let mut crossing = None;
+            for j in i + 2..ops.len() {
+                let c = op_end(&ops[j - 1]).unwrap();
+                if (c - b).length() > reach {
+                    break;
+                }
+                if let PathOp::LineTo(d) = ops[j] {
+                    if let Some(x) = segment_intersection(a, b, c, d) {
+                        crossing = Some((j, x));
+                        break;
+                    }
+                }
+            }
+            if let Some((j, x)) = crossing {
+                ops[i] = PathOp::LineTo(x);
+                ops.drain(i + 1..j);
+                // the shortened line can cross another loop
+                continue;
+            }
+        }
+        i += 1;
+    }
+}
+
+fn outline_contour(dest: &mut Vec<PathOp>, style: &StrokeStyle, points: &[Point], closed: bool) {
+    let half_width = style.width / 2.;
+    let segment_count = if closed { points.len() } else { points.len() - 1 };
+    let segment = |i: usize| (points[i % points.len()], points[(i + 1) % points.len()]);
+    let normals: Vec<Vector> = (0..segment_count)
+        .map(|i| {
+            let (a, b) = segment(i);
+            compute_normal(a, b).unwrap()
+        })
+        .collect();
+
+    // Closed contours start in the middle of the first segment so that the
+    // join at the first point is handled like all of the others.
+    let start_t = if closed { 0.5 } else { 0. };
+    let (a, b) = segment(0);
+    let start = a.lerp(b, start_t);
+    let mut left = PathBuilder::new();
+    let mut right = PathBuilder::new();
+    left.move_to(start.x + normals[0].x * half_width, start.y + normals[0].y * half_width);
+    right.move_to(start.x - normals[0].x * half_width, start.y - normals[0].y * half_width);
+
+    let mut left_range = SideRange { start: start_t, end: 1. };
+    let mut right_range = left_range;
+    let join_count = if closed { segment_count } else { segment_count - 1 };
+    for i in 0..join_count {
+        let next = (i + 1) % segment_count;
+        let (n1, n2) = (normals[i], normals[next]);
+        let seg1 = segment(i);
+        let seg2 = segment(next);
+        let pt = seg1.1;
+        // the last join needs to stop at the start point of the contour
+        let next_end = if closed && next == 0 { start_t } else { 1. };
+        if n1 == n2 {
+            left.line_to(pt.x + n1.x * half_width, pt.y + n1.y * half_width);
+            right.line_to(pt.x - n1.x * half_width, pt.y - n1.y * half_width);
+            left_range = SideRange { start: 0., end: next_end };
+            right_range = left_range;
+            continue;
+        }
+        if is_interior_angle(n1, n2) {
+            // the outside of the corner is on the right
+            let t = outline_inner_join(&mut left, half_width, seg1, seg2, (n1, n2), left_range, next_end);
+            left_range = SideRange { start: t, end: next_end };
+            right.line_to(pt.x - n1.x * half_width, pt.y - n1.y * half_width);
+            outline_outer_join(&mut right, style, pt, flip(n1), flip(n2), seg1.1 - seg1.0);
+            right_range = SideRange { start: 0., end: next_end };
+        } else {
+            left.line_to(pt.x + n1.x * half_width, pt.y + n1.y * half_width);
+            outline_outer_join(&mut left, style, pt, n1, n2, seg1.1 - seg1.0);
+            left_range = SideRange { start: 0., end: next_end };
+            let t = outline_inner_join(&mut right, half_width, seg1, seg2, (flip(n1), flip(n2)), right_range, next_end);
+            right_range = SideRange { start: t, end: next_end };
+        }
+    }
+
+    let (end, n) = if closed {
+        (start, normals[0])
+    } else {
+        (points[points.len() - 1], normals[segment_count - 1])
+    };
+    left.line_to(end.x + n.x * half_width, end.y + n.y * half_width);
+    right.line_to(end.x - n.x * half_width, end.y - n.y * half_width);
+    let mut left = left.finish();
+    let mut right = right.finish();
+    remove_inner_loops(&mut left.ops, 2. * style.width);
+    remove_inner_loops(&mut right.ops, 2. * style.width);
+
+    if closed {
+        left.ops.push(PathOp::Close);
+        right.ops.push(PathOp::Close);
+        dest.extend(left.ops);
+        dest.extend(right.reverse().ops);
+    } else {
+        let mut outline = PathBuilder::from(left);
+        outline_cap(&mut outline, style, end, n);
+        let mut outline = outline.finish();
+        // the reversed right side starts where the cap ended
+        outline.ops.extend(right.reverse().ops.into_iter().skip(1));
+        let mut outline = PathBuilder::from(outline);
+        outline_cap(&mut outline, style, start, flip(normals[0]));
+        outline.close();
+        dest.extend(outline.finish().ops);
+    }
+}
+
+/// Strokes `path` with `style` like `stroke_to_path` but produces a single outline
+/// for each open subpath (left side, end cap, right side reversed, start cap) and a
+/// pair of outlines with opposite directions for each closed subpath. Joins are merged
+/// into the outline instead of being separate subpaths, so the result can be filled with
+/// `Winding::EvenOdd` or exported. Parts of the path that come back within the stroke width
+/// of each other, other than neighbouring segments, still overlap. Curves are flattened within the same tolerance that
+/// `stroke_to_path` uses for their offsets so the outline is made of lines.
+pub fn stroke_to_outline(path: &Path, style: &StrokeStyle) -> Path {
+    let mut ops = Vec::new();
+    if style.width <= 0. {
+        return Path { ops, winding: Winding::NonZero };
+    }
+
+    for contour in path.flatten(CURVE_TOLERANCE).contours() {
+        let mut points: Vec<Point> = Vec::new();
+        let mut closed = false;
+        for op in &contour.ops {
+            match *op {
+                PathOp::MoveTo(pt) | PathOp::LineTo(pt) => points.push(pt),
+                PathOp::Close => closed = true,
+                PathOp::QuadTo(..) | PathOp::CubicTo(..) => unreachable!(),
+            }
+        }
+        // zero length segments don't have a direction so we drop them
+        points.dedup();
+        if closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < 2 {
+            continue;
+        }
+        outline_contour(&mut ops, style, &points, closed);
+    }
+    Path { ops, winding: Winding::NonZero }
+}
+
+// Lines are split at most this many times to follow the changes of a width function
+const MAX_WIDTH_SUBDIVISIONS: u32 = 8;
+
+// A flattened subpath with the stroke width at each of its points. Closed subpaths
+// end with their first point so that the closing segment can have its own width.
+struct WidthContour {
+    points: Vec<(Point, f32)>,
+    closed: bool,
+}
+
+// Returns `path` with a `MoveTo` in front of the segments that don't have a current point.
+// Like `stroke_to_path`, they start at their first point, or at the start of the subpath
+// after a `Close`.
+fn with_current_points(path: &Path) -> Path {
+    let mut ops = Vec::with_capacity(path.ops.len());
+    let mut cur_pt = None;
+    let mut start = None;
+    for op in &path.ops {
+        let first = match *op {
+            PathOp::MoveTo(pt) => {
+                start = Some(pt);
+                None
+            }
+            PathOp::LineTo(pt) => Some(pt),
+            PathOp::QuadTo(ctrl, _) => Some(ctrl),
+            PathOp::CubicTo(ctrl1, ..) => Some(ctrl1),
+            PathOp::Close => {
+                cur_pt = None;
+                ops.push(*op);
+                continue;
+            }
+        };
+        if let (None, Some(first)) = (cur_pt, first) {
+            let pt = start.unwrap_or(first);
+            start = Some(pt);
+            ops.push(PathOp::MoveTo(pt));
+        }
+        cur_pt = op_end(op);
+        ops.push(*op);
+    }
+    Path { ops, winding: path.winding }
+}
+
+fn op_end(op: &PathOp) -> Option<Point> {
+    match *op {
+        PathOp::MoveTo(pt) | PathOp::LineTo(pt) | PathOp::QuadTo(_, pt) | PathOp::CubicTo(_, _, pt) => Some(pt),
+        PathOp::Close => None,
+    }
+}
+
+/// Strokes `path` with a width that varies along it and returns the area covered by the stroke.
+/// `width` is called with the distance along the path, measured across all of its subpaths, and
+/// returns the width of the stroke at that point. The joins and caps of `style` are used with the
+/// width at the point they are at while `style.width` and the dash array are ignored. Curves and
+/// changes of the width are followed within `tolerance`. The result can be drawn with
+/// `DrawTarget::fill` or the whole thing can be done with `DrawTarget::stroke_with_width`.
+pub fn stroke_to_path_with_width(path: &Path, style: &StrokeStyle, tolerance: f32, width: impl Fn(f32) -> f32) -> Path {
+    let width = |distance| width(distance).max(0.);
+    let mut contours: Vec<WidthContour> = Vec::new();
+    let mut distance = 0.;
+    for op in &with_current_points(path).flatten(tolerance).ops {
+        match *op {
+            PathOp::MoveTo(pt) => contours.push(WidthContour { points: vec![(pt, width(distance))], closed: false }),
+            PathOp::LineTo(pt) => {
+                // with_current_points made sure that there's an open subpath
+                let contour = contours.last_mut().unwrap();
+                let (from, from_width) = contour.points[contour.points.len() - 1];
+                let to_distance = distance + (pt - from).length();
+                sample_width(&mut contour.points, &width, tolerance, (from, distance, from_width), (pt, to_distance, width(to_distance)), 0);
+                distance = to_distance;
+            }
+            PathOp::Close => {
+                if let Some(contour) = contours.last_mut() {
+                    if !contour.closed {
+                        let (from, from_width) = contour.points[contour.points.len() - 1];
+                        let start = contour.points[0].0;
+                        let to_distance = distance + (start - from).length();
+                        sample_width(&mut contour.points, &width, tolerance, (from, distance, from_width), (start, to_distance, width(to_distance)), 0);
+                        distance = to_distance;
+                        contour.closed = true;
+                    }
+                }
+            }
+            PathOp::QuadTo(..) | PathOp::CubicTo(..) => unreachable!(),
+        }
+    }
+    stroke_width_contours(&contours, style)
+}
+
+/// Strokes `path` with a width that varies along it and returns the area covered by the stroke.
+/// `widths` has the width of the stroke at each point of the path, i.e. one for each `MoveTo`,
+/// `LineTo`, `QuadTo` and `CubicTo`, and is interpolated along the length of the segments in between.
+/// If there are fewer widths than points the last width is used for the rest. The joins and caps of
+/// `style` are used with the width at the point they are at while `style.width` and the dash array are
+/// ignored. Curves are flattened within `tolerance`. The result can be drawn with `DrawTarget::fill`
+/// or the whole thing can be done with `DrawTarget::stroke_with_widths`.
+pub fn stroke_to_path_with_widths(path: &Path, style: &StrokeStyle, tolerance: f32, widths: &[f32]) -> Path {
+    let mut contours: Vec<WidthContour> = Vec::new();
+    let mut index = 0;
+    let mut next_width = || {
+        let width = widths.get(index).or_else(|| widths.last()).map_or(0., |w| w.max(0.));
+        index += 1;
+        width
+    };
+    let mut cur_pt: Option<Point> = None;
+    let mut pieces = Vec::new();
+    for op in &path.ops {
+        pieces.clear();
+        // like stroke_to_path, a segment without a current point starts at its first point
+        let (from, to) = match *op {
+            PathOp::MoveTo(pt) => {
+                contours.push(WidthContour { points: vec![(pt, next_width())], closed: false });
+                cur_pt = Some(pt);
+                continue;
+            }
+            PathOp::LineTo(pt) => {
+                pieces.push(pt);
+                (cur_pt.unwrap_or(pt), pt)
+            }
+            PathOp::QuadTo(ctrl, to) => {
+                let from = cur_pt.unwrap_or(ctrl);
+                QuadraticBezierSegment { from, ctrl, to }.for_each_flattened(tolerance, &mut |l| pieces.push(l.to));
+                (from, to)
+            }
+            PathOp::CubicTo(ctrl1, ctrl2, to) => {
+                let from = cur_pt.unwrap_or(ctrl1);
+                CubicBezierSegment { from, ctrl1, ctrl2, to }.for_each_flattened(tolerance, &mut |l| pieces.push(l.to));
+                (from, to)
+            }
+            PathOp::Close => {
+                if let Some(contour) = contours.last_mut() {
+                    if !contour.closed {
+                        let first = contour.points[0];
+                        contour.points.push(first);
+                        contour.closed = true;
+                        cur_pt = Some(first.0);
+                    }
+                }
+                continue;
+            }
+        };
+        let width = next_width();
+        let contour = match contours.last_mut() {
+            Some(contour) if !contour.closed => contour,
+            _ => {
+                // start a new subpath at the start of the segment. After a close that's the
+                // start of the closed subpath and it keeps its width.
+                let start_width = contours.last().map_or(width, |c| c.points[0].1);
+                contours.push(WidthContour { points: vec![(from, start_width)], closed: false });
+                contours.last_mut().unwrap()
+            }
+        };
+        let (from, from_width) = contour.points[contour.points.len() - 1];
+        let mut prev = from;
+        let lengths: Vec<f32> = pieces.iter().map(|&pt| {
+            let length = (pt - prev).length();
+            prev = pt;
+            length
+        }).collect();
+        let total: f32 = lengths.iter().sum();
+        let mut distance = 0.;
+        for (&pt, length) in pieces.iter().zip(lengths) {
+            distance += length;
+            let t = if total > 0. { distance / total } else { 1. };
+            contour.points.push((pt, from_width + (width - from_width) * t));
+        }
+        cur_pt = Some(to);
+    }
+    stroke_width_contours(&contours, style)
+}
+
+// Adds points between `from` and `to` until linearly interpolating the width between them
+// is close enough to `width`. `to` is added last.
+fn sample_width(
+    points: &mut Vec<(Point, f32)>,
+    width: &impl Fn(f32) -> f32,
+    tolerance: f32,
+    (from, from_distance, from_width): (Point, f32, f32),
+    (to, to_distance, to_width): (Point, f32, f32),
+    depth: u32,
+) {
+    if depth < MAX_WIDTH_SUBDIVISIONS {
+        let distance = (from_distance + to_distance) / 2.;
+        let mid_width = width(distance);
+        // the edges of the stroke are half the width from the center
+        if (mid_width - (from_width + to_width) / 2.).abs() / 2. > tolerance {
+            let mid = from.lerp(to, 0.5);
+            sample_width(points, width, tolerance, (from, from_distance, from_width), (mid, distance, mid_width), depth + 1);
+            sample_width(points, width, tolerance, (mid, distance, mid_width), (to, to_distance, to_width), depth + 1);
+            return;
+        }
+    }
+    points.push((to, to_width));
+}
+
+fn stroke_width_contours(contours: &[WidthContour], style: &StrokeStyle) -> Path {
+    let mut stroked_path = PathBuilder::new();
+    // joins and caps use the width at the point they are at
+    let mut vertex_style = StrokeStyle { dash_array: Vec::new(), ..style.clone() };
+    for contour in contours {
+        let mut start = None;
+        let mut last_normal = Vector::zero();
+        for segment in contour.points.windows(2) {
+            let ((from, from_width), (to, to_width)) = (segment[0], segment[1]);
+            if let Some(normal) = compute_normal(from, to) {
+                if start.is_none() {
+                    start = Some((from, normal, from_width));
+                } else {
+                    vertex_style.width = from_width;
+                    join_line(&mut stroked_path, &vertex_style, from, last_normal, normal, (0., 0.));
+                }
+                stroke_tapered_line(&mut stroked_path, (from, from_width / 2.), (to, to_width / 2.), normal);
+                last_normal = normal;
+            }
+        }
+
+        if let Some((start, start_normal, start_width)) = start {
+            let (end, end_width) = contour.points[contour.points.len() - 1];
+            vertex_style.width = end_width;
+            if contour.closed {
+                join_line(&mut stroked_path, &vertex_style, end, last_normal, start_normal, (0., 0.));
+            } else {
+                cap_line(&mut stroked_path, &vertex_style, end, last_normal);
+                vertex_style.width = start_width;
+                cap_line(&mut stroked_path, &vertex_style, start, flip(start_normal));
+            }
+        }
     }
     stroked_path.finish()
 }
@@ -956,4 +956,1650 @@ mod tests {
             &checkerboard[..]
         );
     }
+
+    #[test]
+    fn path_bounds() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        pb.quad_to(10., 20., 20., 0.);
+        pb.cubic_to(20., -10., 30., -10., 30., 0.);
+        let path = pb.finish();
+
+        let control = path.control_bounds().unwrap();
+        assert_eq!(control, Rect::new(Point::new(0., -10.), Point::new(30., 20.)));
+
+        let tight = path.bounds().unwrap();
+        assert_eq!(tight.min.x, 0.);
+        assert_eq!(tight.max.x, 30.);
+        assert!((tight.max.y - 10.).abs() < 0.001);
+        assert!((tight.min.y + 7.5).abs() < 0.001);
+
+        let scaled = path.transformed_bounds(&Transform::scale(2., 1.)).unwrap();
+        assert_eq!(scaled.max.x, 60.);
+
+        assert!(PathBuilder::new().finish().bounds().is_none());
+    }
+
+    #[test]
+    fn path_stroke_bounds() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(10., 10.);
+        pb.line_to(20., 10.);
+        let path = pb.finish();
+
+        let style = StrokeStyle {
+            width: 4.,
+            join: LineJoin::Round,
+            ..Default::default()
+        };
+        let bounds = path.stroke_bounds(&style, &Transform::identity()).unwrap();
+        assert_eq!(bounds, Rect::new(Point::new(8., 8.), Point::new(22., 12.)));
+
+        let bounds = path.stroke_bounds(&style, &Transform::translation(5., 0.)).unwrap();
+        assert_eq!(bounds, Rect::new(Point::new(13., 8.), Point::new(27., 12.)));
+
+        let style = StrokeStyle { width: 0., ..Default::default() };
+        assert!(path.stroke_bounds(&style, &Transform::identity()).is_none());
+    }
+
+    #[test]
+    fn path_contains_point_in_stroke() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(10., 10.);
+        pb.line_to(30., 10.);
+        pb.line_to(30., 30.);
+        let path = pb.finish();
+
+        let mut style = StrokeStyle {
+            width: 4.,
+            ..Default::default()
+        };
+        assert!(path.contains_point_in_stroke(&style, 0.1, 20., 11.5));
+        assert!(!path.contains_point_in_stroke(&style, 0.1, 20., 13.));
+        // the miter corner
+        assert!(path.contains_point_in_stroke(&style, 0.1, 31.5, 8.5));
+        // past the butt cap
+        assert!(!path.contains_point_in_stroke(&style, 0.1, 9., 10.));
+
+        style.cap = LineCap::Square;
+        assert!(path.contains_point_in_stroke(&style, 0.1, 9., 10.));
+
+        style.join = LineJoin::Round;
+        assert!(!path.contains_point_in_stroke(&style, 0.1, 31.9, 8.1));
+
+        style.dash_array = vec![5., 5.];
+        style.cap = LineCap::Butt;
+        assert!(path.contains_point_in_stroke(&style, 0.1, 12., 10.));
+        assert!(!path.contains_point_in_stroke(&style, 0.1, 17., 10.));
+    }
+
+    #[test]
+    fn path_segments() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        pb.line_to(10., 0.);
+        pb.quad_to(10., 10., 0., 10.);
+        pb.close();
+        pb.line_to(5., 5.);
+        let segments: Vec<_> = pb.finish().segments().collect();
+        assert_eq!(segments, vec![
+            Segment::Line(lyon_geom::LineSegment { from: Point::new(0., 0.), to: Point::new(10., 0.) }),
+            Segment::Quadratic(lyon_geom::QuadraticBezierSegment {
+                from: Point::new(10., 0.),
+                ctrl: Point::new(10., 10.),
+                to: Point::new(0., 10.),
+            }),
+            Segment::Line(lyon_geom::LineSegment { from: Point::new(0., 10.), to: Point::new(0., 0.) }),
+            Segment::Line(lyon_geom::LineSegment { from: Point::new(0., 0.), to: Point::new(5., 5.) }),
+        ]);
+
+        let path: Path = vec![PathOp::MoveTo(Point::new(1., 1.)), PathOp::LineTo(Point::new(2., 2.))].into_iter().collect();
+        assert_eq!(path.segments().count(), 1);
+    }
+
+    #[cfg(feature = "lyon_path")]
+    #[test]
+    fn lyon_path_round_trip() {
+        let mut pb = PathBuilder::new();
+        pb.line_to(0., 0.);
+        pb.line_to(10., 0.);
+        pb.cubic_to(10., 5., 5., 10., 0., 10.);
+        pb.close();
+        pb.line_to(5., 5.);
+        let path = pb.finish();
+
+        let lyon: lyon_path::Path = (&path).into();
+        let round_trip: Path = lyon.into();
+        let expected: Vec<_> = path.segments().collect();
+        assert_eq!(round_trip.segments().collect::<Vec<_>>(), expected);
+        assert!(matches!(round_trip.ops[0], PathOp::MoveTo(_)));
+        // the subpath after the close starts with an explicit move
+        assert!(matches!(round_trip.ops[4], PathOp::MoveTo(_)));
+    }
+
+    #[cfg(feature = "kurbo")]
+    #[test]
+    fn kurbo_round_trip() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        pb.quad_to(10., 0., 10., 10.);
+        pb.close();
+        pb.line_to(5., 5.);
+        let path = pb.finish();
+
+        let bez: kurbo::BezPath = path.clone().into();
+        assert_eq!(bez.elements().len(), 5);
+        let round_trip = Path::from(&bez);
+        assert_eq!(round_trip.segments().collect::<Vec<_>>(), path.segments().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn path_direction() {
+        let mut pb = PathBuilder::new();
+        pb.rect(0., 0., 4., 4.);
+        pb.move_to(1., 1.);
+        pb.line_to(3., 1.);
+        pb.line_to(3., 3.);
+        let path = pb.finish();
+
+        let contours = path.contours();
+        assert_eq!(contours.len(), 2);
+        assert_eq!(contours[0].signed_area(), 16.);
+        assert!(contours[0].is_clockwise());
+        // the open contour is implicitly closed
+        assert_eq!(contours[1].signed_area(), 2.);
+
+        let reversed = path.reverse();
+        assert_eq!(reversed.signed_area(), -18.);
+        let contours = reversed.contours();
+        assert!(!contours[0].is_clockwise());
+        assert!(matches!(contours[0].ops[0], PathOp::MoveTo(p) if p == Point::new(0., 0.)));
+        assert!(matches!(contours[1].ops[0], PathOp::MoveTo(p) if p == Point::new(3., 3.)));
+        assert_eq!(reversed.reverse().signed_area(), 18.);
+
+        // curves should match their flattened equivalent
+        let mut pb = PathBuilder::new();
+        pb.arc(0., 0., 10., 0., 2. * std::f32::consts::PI);
+        pb.move_to(20., 0.);
+        pb.cubic_to(30., 0., 30., 10., 20., 20.);
+        let path = pb.finish();
+        let area = path.signed_area();
+        assert!((area - path.flatten(0.001).signed_area()).abs() < 0.1);
+    }
+
+    #[test]
+    fn reversed_hole() {
+        let mut dt = DrawTarget::new(3, 1);
+        let mut pb = PathBuilder::new();
+        pb.rect(0., 0., 3., 1.);
+        let mut hole = PathBuilder::new();
+        hole.rect(1., 0., 1., 1.);
+        let hole = hole.finish();
+        // the hole has the same direction as the outside so it needs reversing
+        assert!(hole.is_clockwise());
+        let mut path = pb.finish();
+        path.ops.extend(hole.reverse().ops);
+        dt.fill(&path, &WHITE_SOURCE, &DrawOptions::new());
+        let white = 0xffffffff;
+        assert_eq!(dt.get_data(), &vec![white, 0, white][..]);
+    }
+
+    fn distance_to_path(path: &Path, pt: Point) -> f32 {
+        path.flatten(0.01).segments().map(|s| match s {
+            Segment::Line(l) => l.distance_to_point(pt),
+            _ => unreachable!(),
+        }).fold(f32::INFINITY, f32::min)
+    }
+
+    #[test]
+    fn simplify_polyline() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        for i in 1..=100 {
+            let jitter = if i % 2 == 0 { 0.05 } else { -0.05 };
+            pb.line_to(i as f32, jitter);
+        }
+        for i in 1..=100 {
+            pb.line_to(100., i as f32);
+        }
+        pb.quad_to(50., 150., 0., 100.);
+        let path = pb.finish();
+
+        let simplified = path.simplify(0.1);
+        assert_eq!(simplified.ops.len(), 4);
+        assert!(matches!(simplified.ops[2], PathOp::LineTo(p) if p == Point::new(100., 100.)));
+        assert!(matches!(simplified.ops[3], PathOp::QuadTo(..)));
+
+        // only the collinear points of the vertical line are removed at a small tolerance
+        assert_eq!(path.simplify(0.01).ops.len(), path.ops.len() - 99);
+    }
+
+    #[test]
+    fn fit_curves_polyline() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        let mut points = Vec::new();
+        for i in 0..=400 {
+            let x = i as f32 / 4.;
+            let pt = Point::new(x, (x / 10.).sin() * 20.);
+            points.push(pt);
+            pb.line_to(pt.x, pt.y);
+        }
+        let path = pb.finish();
+
+        let fitted = path.fit_curves(0.1);
+        assert!(fitted.ops.len() < 25);
+        for pt in points {
+            assert!(distance_to_path(&fitted, pt) < 0.11);
+        }
+    }
+
+    #[test]
+    fn fit_curves_keeps_corners() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        for i in 1..=10 {
+            pb.line_to(i as f32, 0.);
+        }
+        for i in 1..=10 {
+            pb.line_to(10., i as f32);
+        }
+        pb.line_to(0., 10.);
+        pb.close();
+        let path = pb.finish();
+
+        let fitted = path.fit_curves(0.1);
+        // one cubic for each side and the closing side
+        assert_eq!(fitted.ops.len(), 6);
+        assert!(matches!(fitted.ops[1], PathOp::CubicTo(_, _, p) if p == Point::new(10., 0.)));
+        assert!(matches!(fitted.ops[5], PathOp::Close));
+        assert!(distance_to_path(&fitted, Point::new(5., 10.)) < 0.01);
+        assert!((fitted.signed_area() - 100.).abs() < 0.01);
+    }
+
+    // Compares the filled areas of two paths by sampling a grid of points that avoids
+    // lying exactly on the edges. Rasterizing isn't precise enough for this because
+    // the same edge with different end points can produce slightly different coverage.
+    fn assert_same_area(a: &Path, b: &Path) {
+        // flattening doesn't preserve the winding
+        let a = Path { winding: a.winding, ..a.flatten(0.01) };
+        let b = Path { winding: b.winding, ..b.flatten(0.01) };
+        for y in 0..80 {
+            for x in 0..80 {
+                let (x, y) = (x as f32 / 2. + 0.0371, y as f32 / 2. + 0.0913);
+                assert_eq!(a.contains_point(0.01, x, y), b.contains_point(0.01, x, y), "{}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn stroke_outline_matches_stroke() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(5., 5.);
+        pb.line_to(30., 8.);
+        pb.line_to(12., 20.);
+        pb.line_to(32., 34.);
+        pb.move_to(3., 30.);
+        pb.line_to(10., 36.);
+        pb.line_to(3., 36.);
+        pb.close();
+        let path = pb.finish();
+
+        let joins = [LineJoin::Round, LineJoin::Miter, LineJoin::Bevel, LineJoin::MiterClip, LineJoin::Arcs];
+        for &join in &joins {
+            for &cap in &[LineCap::Round, LineCap::Square, LineCap::Butt, LineCap::Triangle] {
+                let style = StrokeStyle { width: 3., join, cap, miter_limit: 2., ..Default::default() };
+                let mut outline = stroke_to_outline(&path, &style);
+                // one outline for the open subpath and two for the closed one
+                assert_eq!(outline.contours().len(), 3);
+                let expected = stroke_to_path(&path, &style);
+                assert_same_area(&outline, &expected);
+                outline.winding = Winding::EvenOdd;
+                assert_same_area(&outline, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stroke_outline_short_segments() {
+        // segments that are shorter than the stroke is wide don't make the inside of the
+        // outline overlap itself, so even-odd filling doesn't leave holes
+        let mut pb = PathBuilder::new();
+        pb.move_to(4., 10.);
+        pb.line_to(20., 10.);
+        pb.line_to(22., 13.);
+        pb.line_to(36., 13.);
+        pb.move_to(4., 24.);
+        pb.line_to(20., 24.);
+        pb.line_to(21., 27.);
+        pb.line_to(6., 34.);
+        let path = pb.finish();
+
+        for &join in &[LineJoin::Round, LineJoin::Miter, LineJoin::Bevel] {
+            for &cap in &[LineCap::Round, LineCap::Butt] {
+                let style = StrokeStyle { width: 10., join, cap, ..Default::default() };
+                let mut outline = stroke_to_outline(&path, &style);
+                let expected = stroke_to_path(&path, &style);
+                assert_same_area(&outline, &expected);
+                outline.winding = Winding::EvenOdd;
+                assert_same_area(&outline, &expected);
+            }
+        }
+    }
+
+    #[test]
+    fn stroke_outline_curves() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(5., 20.);
+        pb.quad_to(20., 0., 35., 20.);
+        pb.cubic_to(40., 35., 10., 25., 20., 38.);
+        let path = pb.finish();
+        let style = StrokeStyle { width: 3., ..Default::default() };
+        // curves are flattened before they are outlined
+        let outline = stroke_to_outline(&path, &style);
+        assert_eq!(outline.contours().len(), 1);
+        assert_eq!(outline.ops, stroke_to_outline(&path.flatten(0.1), &style).ops);
+    }
+
+    #[test]
+    fn stroke_outline_closed_rect() {
+        let mut pb = PathBuilder::new();
+        pb.rect(10., 10., 20., 20.);
+        let style = StrokeStyle { width: 4., ..Default::default() };
+        let outline = stroke_to_outline(&pb.finish(), &style);
+        // the inside and outside loops go in opposite directions
+        let contours = outline.contours();
+        assert_eq!(contours.len(), 2);
+        assert_eq!(contours[0].signed_area(), 16. * 16.);
+        assert_eq!(contours[1].signed_area(), -24. * 24.);
+
+        let mut dt = DrawTarget::new(40, 40);
+        let mut path = outline;
+        path.winding = Winding::EvenOdd;
+        dt.fill(&path, &WHITE_SOURCE, &DrawOptions::new());
+        let mut expected = DrawTarget::new(40, 40);
+        let mut pb = PathBuilder::new();
+        pb.rect(8., 8., 24., 24.);
+        pb.rect(12., 12., 16., 16.);
+        let mut path = pb.finish();
+        path.winding = Winding::EvenOdd;
+        expected.fill(&path, &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+    }
+
+    #[test]
+    fn stroke_curves() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(5., 20.);
+        pb.quad_to(20., 0., 35., 20.);
+        // an inflection
+        pb.cubic_to(40., 35., 10., 25., 20., 38.);
+        // a cusp
+        pb.move_to(3., 3.);
+        pb.cubic_to(30., 12., 5., 12., 30., 3.);
+        let path = pb.finish();
+        let center = path.flatten(0.001);
+
+        let style = StrokeStyle { width: 5., join: LineJoin::Round, cap: LineCap::Round, ..Default::default() };
+        let stroked = stroke_to_path(&path, &style);
+        assert!(stroked.ops.iter().any(|op| matches!(op, PathOp::CubicTo(..))));
+        assert!(stroked.ops.len() < stroke_to_path(&center, &style).ops.len() / 4);
+
+        // with round joins and caps the stroke covers everything within half the width of the path
+        let stroked = Path { winding: Winding::NonZero, ..stroked.flatten(0.01) };
+        for y in 0..90 {
+            for x in 0..90 {
+                let pt = Point::new(x as f32 / 2. + 0.0371, y as f32 / 2. + 0.0913);
+                let distance = distance_to_path(&center, pt);
+                if distance < 2.3 {
+                    assert!(stroked.contains_point(0.01, pt.x, pt.y), "{:?}", pt);
+                } else if distance > 2.7 {
+                    assert!(!stroked.contains_point(0.01, pt.x, pt.y), "{:?}", pt);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stroke_anisotropic_transform() {
+        let mut pb = PathBuilder::new();
+        pb.arc(2., 2., 1.5, 0., 2. * std::f32::consts::PI);
+        pb.close();
+        let path = pb.finish();
+        let styles = [
+            // dashing splits and measures the curves in user space
+            StrokeStyle { width: 1., dash_array: vec![3., 1.], ..Default::default() },
+            // the stroke is wider than the circle so the curve stroker flattens it in user space
+            StrokeStyle { width: 4., ..Default::default() },
+        ];
+        let transform = Transform::scale(100., 2.);
+
+        for style in &styles {
+            let mut dt = DrawTarget::new(400, 10);
+            dt.set_transform(&transform);
+            dt.stroke(&path, &WHITE_SOURCE, style, &DrawOptions::new());
+
+            let mut expected = DrawTarget::new(400, 10);
+            expected.set_transform(&transform);
+            expected.stroke(&path.flatten(0.0001), &WHITE_SOURCE, style, &DrawOptions::new());
+
+            let max_difference = dt.get_data().iter().zip(expected.get_data())
+                .map(|(a, b)| ((a & 0xff) as i32 - (b & 0xff) as i32).abs())
+                .max()
+                .unwrap();
+            // differences of one row of samples are expected but faceting shows up as more than that
+            assert!(max_difference < 96, "{}", max_difference);
+        }
+    }
+
+    #[test]
+    fn stroke_non_scaling() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(1., 1.);
+        pb.line_to(9., 9.);
+        pb.quad_to(1., 9., 1., 1.);
+        let path = pb.finish();
+        let style = StrokeStyle { width: 3., ..Default::default() };
+
+        let mut dt = DrawTarget::new(40, 40);
+        dt.set_transform(&Transform::scale(4., 4.));
+        dt.stroke_non_scaling(&path, &WHITE_SOURCE, &style, &DrawOptions::new());
+
+        let mut expected = DrawTarget::new(40, 40);
+        expected.stroke(&path.transform(&Transform::scale(4., 4.)), &WHITE_SOURCE, &style, &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+    }
+
+    #[test]
+    fn hairline() {
+        for &scale in &[0.5, 1., 10.] {
+            let mut pb = PathBuilder::new();
+            pb.move_to(2. / scale, 5.5 / scale);
+            pb.line_to(8. / scale, 5.5 / scale);
+            let path = pb.finish();
+
+            let mut dt = DrawTarget::new(10, 10);
+            dt.set_transform(&Transform::scale(scale, scale));
+            dt.stroke_hairline(&path, &WHITE_SOURCE, &DrawOptions::new());
+            for y in 0..10 {
+                for x in 0..10 {
+                    let expected = if y == 5 && (2..8).contains(&x) { 0xffffffff } else { 0 };
+                    assert_eq!(dt.get_data()[y * 10 + x], expected, "{} {} {}", scale, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hairline_antialiasing() {
+        let mut pb = PathBuilder::new();
+        // a line between two rows of pixels
+        pb.move_to(1., 2.);
+        pb.line_to(9., 2.);
+        // a diagonal line
+        pb.move_to(1., 4.);
+        pb.line_to(9., 8.);
+        let path = pb.finish();
+
+        let mut dt = DrawTarget::new(10, 10);
+        dt.stroke_hairline(&path, &WHITE_SOURCE, &DrawOptions::new());
+        let alpha = |x: usize, y: usize| dt.get_data()[y * 10 + x] >> 24;
+        for x in 1..9 {
+            assert_eq!(alpha(x, 1), 128);
+            assert_eq!(alpha(x, 2), 128);
+            // every column crossed by the diagonal gets a pixel worth of coverage
+            let total: u32 = (3..10).map(|y| alpha(x, y)).sum();
+            assert!((254..=256).contains(&total), "{} {}", x, total);
+        }
+
+        let mut dt = DrawTarget::new(10, 10);
+        dt.stroke_hairline(&path, &WHITE_SOURCE, &DrawOptions { antialias: AntialiasMode::None, ..DrawOptions::new() });
+        for x in 1..9 {
+            let column: Vec<u32> = (0..10).map(|y| dt.get_data()[y * 10 + x]).collect();
+            assert_eq!(column.iter().filter(|&&p| p == 0xffffffff).count(), 2);
+            assert_eq!(column.iter().filter(|&&p| p == 0).count(), 8);
+        }
+    }
+
+    #[test]
+    fn hairline_offscreen() {
+        // only the part of a line that is near the target is walked so gridlines drawn at
+        // a large zoom don't take forever, even when their end points don't fit in an i32
+        let mut pb = PathBuilder::new();
+        pb.move_to(-1e9, 5.5);
+        pb.line_to(1e9, 5.5);
+        pb.move_to(3.5, -1e12);
+        pb.line_to(3.5, 1e12);
+        let path = pb.finish();
+        let mut dt = DrawTarget::new(10, 10);
+        dt.stroke_hairline(&path, &WHITE_SOURCE, &DrawOptions::new());
+        for y in 0..10 {
+            for x in 0..10 {
+                let expected = if y == 5 || x == 3 { 0xffffffff } else { 0 };
+                assert_eq!(dt.get_data()[y * 10 + x], expected, "{} {}", x, y);
+            }
+        }
+
+        // coverage adds up so a line split in the middle of a pixel covers it once
+        let mut pb = PathBuilder::new();
+        pb.move_to(1., 2.5);
+        pb.line_to(5.5, 2.5);
+        pb.line_to(9., 2.5);
+        let mut dt = DrawTarget::new(10, 10);
+        dt.stroke_hairline(&pb.finish(), &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data()[2 * 10 + 5] >> 24, 255);
+    }
+
+    #[test]
+    fn join_miter_clip() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(10., 40.);
+        pb.line_to(20., 10.);
+        pb.line_to(30., 40.);
+        let path = pb.finish();
+
+        // the miter is about 3.2 times the half width long so it gets clipped 4 units from the corner
+        let style = StrokeStyle { width: 4., join: LineJoin::MiterClip, miter_limit: 2., ..Default::default() };
+        assert!(path.contains_point_in_stroke(&style, 0.01, 20., 6.1));
+        assert!(!path.contains_point_in_stroke(&style, 0.01, 20., 5.9));
+        // while miter falls back to a bevel
+        let style = StrokeStyle { join: LineJoin::Miter, ..style };
+        assert!(!path.contains_point_in_stroke(&style, 0.01, 20., 7.));
+
+        // within the limit miter-clip is the same as miter
+        let style = StrokeStyle { width: 4., join: LineJoin::Miter, miter_limit: 10., ..Default::default() };
+        let expected = stroke_to_path(&path, &style);
+        assert_eq!(stroke_to_path(&path, &StrokeStyle { join: LineJoin::MiterClip, ..style.clone() }).ops, expected.ops);
+        // arcs between straight lines are miters
+        assert_eq!(stroke_to_path(&path, &StrokeStyle { join: LineJoin::Arcs, ..style }).ops, expected.ops);
+    }
+
+    #[test]
+    fn join_arcs() {
+        fn arc(pb: &mut PathBuilder, center: Point, radius: f32, a0: f32, a1: f32) {
+            let k = 4. / 3. * ((a1 - a0) / 4.).tan() * radius;
+            let (s0, c0) = a0.sin_cos();
+            let (s1, c1) = a1.sin_cos();
+            pb.cubic_to(
+                center.x + radius * c0 - k * s0, center.y + radius * s0 + k * c0,
+                center.x + radius * c1 + k * s1, center.y + radius * s1 - k * c1,
+                center.x + radius * c1, center.y + radius * s1,
+            );
+        }
+
+        // two circles with radius 20 that meet at (0, 16)
+        let angle = 16f32.atan2(12.);
+        let mut pb = PathBuilder::new();
+        pb.move_to(8., 0.);
+        arc(&mut pb, Point::new(-12., 0.), 20., 0., angle);
+        arc(&mut pb, Point::new(12., 0.), 20., std::f32::consts::PI - angle, std::f32::consts::PI);
+        let path = pb.finish();
+
+        // the outer edges meet at (0, sqrt(24^2 - 12^2)) while the miter reaches (0, 21)
+        let style = StrokeStyle { width: 8., join: LineJoin::Arcs, ..Default::default() };
+        assert!(path.contains_point_in_stroke(&style, 0.01, 0., 20.7));
+        assert!(!path.contains_point_in_stroke(&style, 0.01, 0., 20.9));
+        let style = StrokeStyle { join: LineJoin::Miter, ..style };
+        assert!(path.contains_point_in_stroke(&style, 0.01, 0., 20.9));
+    }
+
+    #[test]
+    fn cap_triangle() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(10., 10.);
+        pb.line_to(30., 10.);
+        let path = pb.finish();
+        let style = StrokeStyle { width: 10., cap: LineCap::Triangle, join: LineJoin::Bevel, ..Default::default() };
+        assert!(path.contains_point_in_stroke(&style, 0.01, 34.9, 10.));
+        assert!(path.contains_point_in_stroke(&style, 0.01, 5.1, 10.));
+        assert!(!path.contains_point_in_stroke(&style, 0.01, 35.1, 10.));
+        assert!(!path.contains_point_in_stroke(&style, 0.01, 33., 13.));
+        assert!(path.contains_point_in_stroke(&style, 0.01, 33., 11.));
+        assert_eq!(path.stroke_bounds(&style, &Transform::identity()), Some(Rect::new(Point::new(5., 5.), Point::new(35., 15.))));
+    }
+
+    #[test]
+    fn stroke_joins_without_seams() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(5.3, 5.7);
+        pb.line_to(30.1, 8.2);
+        pb.line_to(12.6, 24.9);
+        pb.line_to(36.2, 31.3);
+        pb.quad_to(54.4, 12.1, 42.3, 5.6);
+        pb.cubic_to(24.1, 42.4, 12.5, 30.2, 5.2, 45.9);
+        let path = pb.finish();
+
+        let joins = [LineJoin::Round, LineJoin::Miter, LineJoin::Bevel, LineJoin::MiterClip, LineJoin::Arcs];
+        for &join in &joins {
+            let style = StrokeStyle { width: 7., join, miter_limit: 2., ..Default::default() };
+            let mut dt = DrawTarget::new(60, 60);
+            dt.stroke(&path, &WHITE_SOURCE, &style, &DrawOptions::new());
+            let stroked = Path { winding: Winding::NonZero, ..stroke_to_path(&path, &style).flatten(0.01) };
+            // pixels that are well inside of the stroke need to be completely covered
+            for y in 1..59 {
+                for x in 1..59 {
+                    let inside = (0..3).all(|i| (0..3).all(|j| {
+                        stroked.contains_point(0.01, x as f32 - 0.5 + i as f32, y as f32 - 0.5 + j as f32)
+                    }));
+                    if inside {
+                        assert_eq!(dt.get_data()[y * 60 + x], 0xffffffff, "{:?} {} {}", join, x, y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn variable_width_stroke() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(10., 20.);
+        pb.line_to(50., 20.);
+        let path = pb.finish();
+        let style = StrokeStyle { cap: LineCap::Round, ..Default::default() };
+
+        let contains = |stroked: &Path, x, y| Path { winding: Winding::NonZero, ..stroked.flatten(0.01) }.contains_point(0.01, x, y);
+        let stroked = stroke_to_path_with_widths(&path, &style, 0.1, &[2., 10.]);
+        assert!(contains(&stroked, 30., 22.9));
+        assert!(!contains(&stroked, 30., 23.1));
+        // the caps use the width at their end of the line
+        assert!(contains(&stroked, 54.9, 20.));
+        assert!(!contains(&stroked, 8.9, 20.));
+
+        let stroked = stroke_to_path_with_width(&path, &style, 0.1, |distance| 10. + (distance / 40. * std::f32::consts::PI).sin() * 8.);
+        assert!(contains(&stroked, 30., 28.9));
+        assert!(!contains(&stroked, 30., 29.1));
+        assert!(contains(&stroked, 20., 27.6));
+        assert!(!contains(&stroked, 20., 28.05));
+    }
+
+    #[test]
+    fn constant_width_stroke() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(5., 5.);
+        pb.line_to(30., 8.);
+        pb.quad_to(5., 15., 12., 20.);
+        pb.line_to(32., 34.);
+        pb.move_to(3., 30.);
+        pb.line_to(10., 36.);
+        pb.line_to(3., 36.);
+        pb.close();
+        let path = pb.finish();
+
+        for &join in &[LineJoin::Round, LineJoin::Miter, LineJoin::Bevel] {
+            let style = StrokeStyle { width: 3., join, cap: LineCap::Square, ..Default::default() };
+            let expected = stroke_to_path(&path.flatten(0.1), &style);
+            assert_same_area(&stroke_to_path_with_width(&path, &style, 0.1, |_| 3.), &expected);
+            assert_same_area(&stroke_to_path_with_widths(&path, &style, 0.1, &[3.]), &expected);
+        }
+    }
+
+    #[test]
+    fn variable_width_stroke_without_current_point() {
+        // like stroke_to_path, a curve without a current point starts at its first control point
+        let (ctrl, to) = (Point::new(10., 30.), Point::new(40., 10.));
+        let path = Path { ops: vec![PathOp::QuadTo(ctrl, to)], winding: Winding::NonZero };
+        let moved = Path { ops: vec![PathOp::MoveTo(ctrl), PathOp::QuadTo(ctrl, to)], winding: Winding::NonZero };
+        let style = StrokeStyle { cap: LineCap::Round, ..Default::default() };
+        assert_eq!(stroke_to_path_with_widths(&path, &style, 0.1, &[3.]).ops,
+                   stroke_to_path_with_widths(&moved, &style, 0.1, &[3.]).ops);
+        assert_eq!(stroke_to_path_with_width(&path, &style, 0.1, |_| 3.).ops,
+                   stroke_to_path_with_width(&moved, &style, 0.1, |_| 3.).ops);
+        assert!(!stroke_to_path_with_widths(&path, &style, 0.1, &[3.]).ops.is_empty());
+    }
+
+    #[test]
+    fn variable_width_stroke_tolerance() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(10., 20.);
+        pb.quad_to(30., 0., 50., 20.);
+        let path = pb.finish();
+        let style = StrokeStyle::default();
+        let width = |distance: f32| 2. + distance / 10.;
+        assert!(stroke_to_path_with_width(&path, &style, 0.01, width).ops.len() > stroke_to_path_with_width(&path, &style, 0.1, width).ops.len());
+        assert!(stroke_to_path_with_widths(&path, &style, 0.01, &[2., 6.]).ops.len() > stroke_to_path_with_widths(&path, &style, 0.1, &[2., 6.]).ops.len());
+
+        // DrawTarget picks the tolerance from the transform
+        let mut dt = DrawTarget::new(500, 250);
+        dt.set_transform(&Transform::scale(10., 10.));
+        dt.stroke_with_width(&path, &WHITE_SOURCE, &style, width, &DrawOptions::new());
+        let mut expected = DrawTarget::new(500, 250);
+        expected.set_transform(&Transform::scale(10., 10.));
+        expected.fill(&stroke_to_path_with_width(&path, &style, 0.01, width), &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+
+        let mut dt = DrawTarget::new(500, 250);
+        dt.set_transform(&Transform::scale(10., 10.));
+        dt.stroke_with_widths(&path, &WHITE_SOURCE, &style, &[2., 6.], &DrawOptions::new());
+        expected.clear(SolidSource::from_unpremultiplied_argb(0, 0, 0, 0));
+        expected.fill(&stroke_to_path_with_widths(&path, &style, 0.01, &[2., 6.]), &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+    }
+
+    #[test]
+    fn dash_dots() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 5.);
+        pb.line_to(40., 5.);
+        let dashed = dash_path(&pb.finish(), &[0., 10.], 0.);
+        assert_eq!(dashed.ops.iter().filter(|op| matches!(op, PathOp::LineTo(..))).count(), 4);
+
+        let style = StrokeStyle { width: 4., cap: LineCap::Round, ..Default::default() };
+        let stroked = stroke_to_path(&dashed, &style);
+        for x in &[0., 10., 20., 30.] {
+            assert!(stroked.contains_point(0.01, x + 1.5, 5.));
+            assert!(stroked.contains_point(0.01, x - 1.5, 5.));
+            assert!(!stroked.contains_point(0.01, x + 2.5, 5.));
+            assert!(!stroked.contains_point(0.01, x + 5., 5.));
+        }
+
+        // butt caps have nothing to draw for zero length dashes
+        let style = StrokeStyle { width: 4., cap: LineCap::Butt, ..Default::default() };
+        assert!(stroke_to_path(&dashed, &style).ops.is_empty());
+    }
+
+    #[test]
+    fn dash_curves() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        pb.cubic_to(30., 0., 50., 20., 50., 50.);
+        let path = pb.finish();
+        let length = path.flatten(0.001).segments().map(|s| match s {
+            Segment::Line(line) => line.length(),
+            _ => unreachable!(),
+        }).sum::<f32>();
+
+        let dashed = dash_path(&path, &[5., 5.], 0.);
+        let contours = dashed.contours();
+        assert!(contours.iter().all(|c| c.ops.iter().all(|op| !matches!(op, PathOp::LineTo(..)))));
+        let dashes: Vec<f32> = contours.iter().filter(|c| c.ops.len() > 1).map(|c| {
+            c.flatten(0.001).segments().map(|s| match s {
+                Segment::Line(line) => line.length(),
+                _ => unreachable!(),
+            }).sum::<f32>()
+        }).collect();
+        assert_eq!(dashes.len(), (length / 10.).ceil() as usize);
+        for dash in &dashes[..dashes.len() - 1] {
+            assert!((dash - 5.).abs() < 0.01, "{}", dash);
+        }
+    }
+
+    #[test]
+    fn dash_path_length() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(0., 0.);
+        pb.line_to(100., 0.);
+        let path = pb.finish();
+        // the path is twice as long as it claims to be so the dashes are twice as long
+        let dashed = dash_path_with_length(&path, &[5., 5.], 5., 50.);
+        assert_eq!(dashed.ops.iter().filter(|op| matches!(op, PathOp::LineTo(..))).count(), 5);
+        assert_eq!(dashed.ops, dash_path(&path, &[10., 10.], 10.).ops);
+
+        // strokes scale the dashes the same way
+        let style = StrokeStyle { width: 4., dash_array: vec![5., 5.], dash_offset: 5., ..Default::default() };
+        let mut dt = DrawTarget::new(100, 10);
+        dt.stroke_with_path_length(&path.clone().transform(&Transform::translation(0., 5.)), &WHITE_SOURCE, &style, 50., &DrawOptions::new());
+        let expected_style = StrokeStyle { width: 4., dash_array: vec![10., 10.], dash_offset: 10., ..Default::default() };
+        let mut expected = DrawTarget::new(100, 10);
+        expected.stroke(&path.clone().transform(&Transform::translation(0., 5.)), &WHITE_SOURCE, &expected_style, &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+    }
+
+    #[test]
+    fn dash_longer_than_closed_path() {
+        let mut pb = PathBuilder::new();
+        pb.rect(10., 10., 100., 100.);
+        let path = pb.finish();
+        let dashed = dash_path(&path, &[1000., 10.], 0.);
+        assert_eq!(dashed.ops.last(), Some(&PathOp::Close));
+        let style = StrokeStyle { width: 2., ..Default::default() };
+        assert_eq!(stroke_to_path(&dashed, &style).ops, stroke_to_path(&path, &style).ops);
+    }
+
+    #[test]
+    fn stroke_path_matches_draw_target() {
+        let mut pb = PathBuilder::new();
+        pb.move_to(5., 5.);
+        pb.quad_to(30., 5., 30., 20.);
+        pb.line_to(10., 25.);
+        let path = pb.finish();
+        let style = StrokeStyle { width: 3., dash_array: vec![7., 2.], ..Default::default() };
+        let transform = Transform::scale(2., 1.5).then_translate(Vector::new(3., 1.));
+
+        let mut dt = DrawTarget::new(80, 50);
+        dt.set_transform(&transform);
+        dt.stroke(&path, &WHITE_SOURCE, &style, &DrawOptions::new());
+
+        let (stroked, bounds) = stroke_path(&path, &style, 0.1, Some(&transform));
+        let mut expected = DrawTarget::new(80, 50);
+        expected.fill(&stroked, &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+
+        let bounds = bounds.unwrap();
+        let stroke_bounds = stroked.bounds().unwrap();
+        assert!(bounds.contains_box(&stroke_bounds), "{:?} {:?}", bounds, stroke_bounds);
+
+        // without a transform the stroke stays in the space of the path
+        let (stroked, _) = stroke_path(&path, &style, 0.1, None);
+        assert!(stroked.bounds().unwrap().max.x < 33.);
+    }
+
+    #[test]
+    fn inverse_fill() {
+        let mut pb = PathBuilder::new();
+        pb.rect(1., 1., 2., 2.);
+        let mut path = pb.finish();
+        path.winding = Winding::InverseNonZero;
+        let white = 0xffffffff;
+        let expected = [
+            white, white, white, white,
+            white, 0, 0, white,
+            white, 0, 0, white,
+            white, white, white, white,
+        ];
+
+        for &antialias in &[AntialiasMode::Gray, AntialiasMode::None] {
+            let mut dt = DrawTarget::new(4, 4);
+            dt.fill(&path, &WHITE_SOURCE, &DrawOptions { antialias, ..DrawOptions::new() });
+            assert_eq!(dt.get_data(), &expected[..]);
+        }
+
+        let mut dt = DrawTarget::new(4, 4);
+        dt.push_clip(&path);
+        dt.fill_rect(0., 0., 4., 4., &WHITE_SOURCE, &DrawOptions::new());
+        dt.pop_clip();
+        assert_eq!(dt.get_data(), &expected[..]);
+
+        assert!(!path.contains_point(0.1, 2., 2.));
+        assert!(path.contains_point(0.1, 0.5, 2.));
+        assert!(path.contains_point(0.1, 1., 2.));
+
+        // an empty inverse path covers everything
+        let mut dt = DrawTarget::new(2, 2);
+        let path = Path { ops: Vec::new(), winding: Winding::InverseEvenOdd };
+        dt.fill(&path, &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[white; 4][..]);
+
+        // even-odd inverse fills paint the holes
+        let mut pb = PathBuilder::new();
+        pb.rect(0., 0., 4., 4.);
+        pb.rect(1., 1., 2., 2.);
+        let mut path = pb.finish();
+        path.winding = Winding::InverseEvenOdd;
+        let mut dt = DrawTarget::new(4, 4);
+        dt.fill(&path, &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[0, 0, 0, 0, 0, white, white, 0, 0, white, white, 0, 0, 0, 0, 0][..]);
+    }
+
+    #[test]
+    fn push_clip_out() {
+        let white = 0xffffffff;
+        let expected = [
+            white, white, white, white,
+            white, 0, 0, white,
+            white, 0, 0, white,
+            white, white, white, white,
+        ];
+        let mut pb = PathBuilder::new();
+        pb.rect(1., 1., 2., 2.);
+        let path = pb.finish();
+
+        let mut dt = DrawTarget::new(4, 4);
+        dt.push_clip_out(&path);
+        dt.fill_rect(0., 0., 4., 4., &WHITE_SOURCE, &DrawOptions::new());
+        dt.pop_clip();
+        assert_eq!(dt.get_data(), &expected[..]);
+
+        let mut dt = DrawTarget::new(4, 4);
+        dt.push_clip_out_rect(intrect(1, 1, 3, 3));
+        dt.fill_rect(0., 0., 4., 4., &WHITE_SOURCE, &DrawOptions::new());
+        dt.pop_clip();
+        assert_eq!(dt.get_data(), &expected[..]);
+
+        // clipping out combines with the existing clip
+        let mut dt = DrawTarget::new(4, 4);
+        dt.push_clip(&path);
+        dt.push_clip_out_rect(intrect(1, 1, 3, 2));
+        dt.fill_rect(0., 0., 4., 4., &WHITE_SOURCE, &DrawOptions::new());
+        dt.pop_clip();
+        dt.pop_clip();
+        assert_eq!(dt.get_data(), &[0, 0, 0, 0, 0, 0, 0, 0, 0, white, white, 0, 0, 0, 0, 0][..]);
+    }
+
+    #[test]
+    fn push_clip_rect_f32() {
+        let white = 0xffffffff;
+        // pixel aligned after the transform
+        let mut dt = DrawTarget::new(3, 3);
+        dt.set_transform(&Transform::translation(0.5, 0.5));
+        dt.push_clip_rect_f32(Rect::new(Point::new(0.5, 0.5), Point::new(1.5, 1.5)));
+        dt.set_transform(&Transform::identity());
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[0, 0, 0, 0, white, 0, 0, 0, 0][..]);
+
+        // fractional edges are antialiased
+        let mut dt = DrawTarget::new(3, 3);
+        dt.push_clip_rect_f32(Rect::new(Point::new(1., 1.), Point::new(2.5, 2.)));
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        let data = dt.get_data();
+        assert_eq!(data[4], white);
+        assert_eq!(data[5] >> 24, 0x80);
+        assert!(data.iter().enumerate().all(|(i, &p)| i == 4 || i == 5 || p == 0));
+
+        // the mask of the current clip is kept
+        let mut pb = PathBuilder::new();
+        pb.rect(0., 0., 2., 2.);
+        let mut dt = DrawTarget::new(3, 3);
+        dt.push_clip(&pb.finish());
+        dt.push_clip_rect_f32(Rect::new(Point::new(1., 1.), Point::new(3., 3.)));
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[0, 0, 0, 0, white, 0, 0, 0, 0][..]);
+
+        // only the bounds of the rect are rasterized but the result matches clipping to its path
+        let rect = Rect::new(Point::new(-4., 3.), Point::new(14., 9.));
+        let mut pb = PathBuilder::new();
+        pb.rect(rect.min.x, rect.min.y, rect.width(), rect.height());
+        let path = pb.finish();
+        let mut results = Vec::new();
+        for &f32_rect in &[true, false] {
+            let mut dt = DrawTarget::new(16, 16);
+            dt.push_clip_rect(intrect(2, 0, 16, 12));
+            dt.set_transform(&Transform::rotation(euclid::Angle::degrees(30.)));
+            if f32_rect {
+                dt.push_clip_rect_f32(rect);
+            } else {
+                dt.push_clip(&path);
+            }
+            dt.set_transform(&Transform::identity());
+            dt.fill_rect(0., 0., 16., 16., &WHITE_SOURCE, &DrawOptions::new());
+            results.push(dt.get_data().to_vec());
+        }
+        assert!(results[0].iter().any(|&p| p != 0));
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[test]
+    fn clip_rect_keeps_mask() {
+        let white = 0xffffffff;
+        let mut pb = PathBuilder::new();
+        pb.rect(0., 0., 2., 2.);
+        let path = pb.finish();
+
+        // rect clips keep the mask of the clip they're combined with
+        let mut dt = DrawTarget::new(3, 3);
+        dt.push_clip(&path);
+        dt.push_clip_rect(intrect(1, 1, 3, 3));
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[0, 0, 0, 0, white, 0, 0, 0, 0][..]);
+
+        // and the mask is still there after they're popped
+        dt.pop_clip();
+        dt.clear(SolidSource::from_unpremultiplied_argb(0, 0, 0, 0));
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[white, white, 0, white, white, 0, 0, 0, 0][..]);
+    }
+
+    #[test]
+    fn aliased_clip() {
+        let white = 0xffffffff;
+        let mut pb = PathBuilder::new();
+        pb.rect(0.2, 0.2, 1.2, 2.);
+        let path = pb.finish();
+
+        let mut dt = DrawTarget::new(3, 3);
+        dt.push_clip_with_antialias(&path, AntialiasMode::None);
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        // the clip matches an aliased fill of the path
+        let mut expected = DrawTarget::new(3, 3);
+        expected.fill(&path, &WHITE_SOURCE, &DrawOptions { antialias: AntialiasMode::None, ..DrawOptions::new() });
+        assert_eq!(dt.get_data(), expected.get_data());
+        assert!(dt.get_data().contains(&white));
+        assert!(dt.get_data().iter().all(|&p| p == 0 || p == white));
+
+        let mut dt = DrawTarget::new(3, 3);
+        dt.push_clip(&path);
+        dt.fill_rect(0., 0., 3., 3., &WHITE_SOURCE, &DrawOptions::new());
+        assert!(dt.get_data()[1] != 0 && dt.get_data()[1] != white);
+    }
+
+    #[test]
+    fn pixel_formats() {
+        fn draw<Backing: AsRef<[F::Pixel]> + AsMut<[F::Pixel]>, F: PixelFormat>(dt: &mut DrawTarget<Backing, F>) {
+            // a background that 565 can represent exactly
+            dt.clear(SolidSource::from_unpremultiplied_argb(0xff, 0x21, 0x41, 0x63));
+            let mut pb = PathBuilder::new();
+            pb.move_to(1., 1.);
+            pb.line_to(7., 2.);
+            pb.line_to(3., 7.);
+            let src = Source::Solid(SolidSource::from_unpremultiplied_argb(0x80, 0xff, 0x80, 0));
+            dt.push_layer(0.5);
+            dt.fill(&pb.finish(), &src, &DrawOptions::new());
+            dt.pop_layer();
+            dt.fill_rect(4., 4., 4., 4., &src, &DrawOptions::new());
+        }
+
+        let mut expected = DrawTarget::new(8, 8);
+        draw(&mut expected);
+        let expected = expected.get_data();
+
+        let mut dt = DrawTarget::<_, Rgba8>::with_format(8, 8);
+        draw(&mut dt);
+        let mut converted = vec![0; 64];
+        Rgba8::load(dt.get_data(), &mut converted);
+        assert_eq!(converted, expected);
+        assert_eq!(dt.get_data()[0], [0x21, 0x41, 0x63, 0xff]);
+
+        let mut dt = DrawTarget::<_, Bgra8>::with_format(8, 8);
+        draw(&mut dt);
+        Bgra8::load(dt.get_data(), &mut converted);
+        assert_eq!(converted, expected);
+
+        let mut dt = DrawTarget::<_, Argb8>::with_format(8, 8);
+        draw(&mut dt);
+        Argb8::load(dt.get_data(), &mut converted);
+        assert_eq!(converted, expected);
+
+        // 565 loses precision so we compare against the expected pixels after storing them.
+        // Every draw rounds so where draws overlap a channel can be a step off.
+        let mut dt = DrawTarget::<_, Rgb565>::with_format(8, 8);
+        draw(&mut dt);
+        let mut stored = vec![0; 64];
+        Rgb565::store(expected, &mut stored);
+        assert_eq!(dt.get_data()[..36], stored[..36]);
+        let mut loaded = vec![0; 64];
+        Rgb565::load(&stored, &mut converted);
+        Rgb565::load(dt.get_data(), &mut loaded);
+        assert_close(&loaded, &converted, 9);
+        // and rounds to the nearest value
+        Rgb565::store(&[0xff0f0f0f], &mut stored[..1]);
+        assert_eq!(stored[0], 2 << 11 | 4 << 5 | 2);
+
+        let mut dt = DrawTarget::<_, A8>::with_format(8, 8);
+        dt.fill_rect(4., 4., 4., 4., &Source::Solid(SolidSource::from_unpremultiplied_argb(0x80, 0xff, 0, 0)), &DrawOptions::new());
+        assert_eq!(dt.get_data()[0], 0);
+        assert_eq!(dt.get_data()[63], 0x80);
+    }
+
+    #[test]
+    fn pixel_formats_blend_directly() {
+        // Rgb565 and A8 draw with SrcOver without converting whole spans to ARGB32. A single
+        // draw gives the same pixels as converting them.
+        fn draw<Backing: AsRef<[F::Pixel]> + AsMut<[F::Pixel]>, F: PixelFormat>(dt: &mut DrawTarget<Backing, F>) {
+            let mut pb = PathBuilder::new();
+            pb.move_to(0., 0.);
+            pb.line_to(8., 0.);
+            pb.line_to(0., 3.5);
+            dt.push_clip(&pb.finish());
+            let mut pb = PathBuilder::new();
+            pb.move_to(0., 0.);
+            pb.line_to(5., 0.);
+            pb.line_to(0., 5.);
+            let src = Source::Solid(SolidSource::from_unpremultiplied_argb(0x80, 0xff, 0x80, 0x10));
+            dt.fill(&pb.finish(), &src, &DrawOptions::new());
+            dt.pop_clip();
+            let src = Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0x10, 0x80, 0xff));
+            dt.fill_rect(4.5, 5.5, 3.5, 2.5, &src, &DrawOptions::new());
+        }
+
+        let background: Vec<u16> = (0..64).map(|i| (i * 0x9e37) as u16).collect();
+        let mut loaded = vec![0; 64];
+        Rgb565::load(&background, &mut loaded);
+        let mut expected = DrawTarget::from_vec(8, 8, loaded);
+        draw(&mut expected);
+        let mut stored = vec![0; 64];
+        Rgb565::store(expected.get_data(), &mut stored);
+        let mut dt = DrawTarget::<_, Rgb565>::from_backing(8, 8, background.clone());
+        draw(&mut dt);
+        assert_eq!(dt.get_data(), &stored[..]);
+        assert_ne!(dt.get_data(), &background[..]);
+
+        let background: Vec<u8> = (0..64).map(|i| (i * 37) as u8).collect();
+        let mut loaded = vec![0; 64];
+        A8::load(&background, &mut loaded);
+        let mut expected = DrawTarget::from_vec(8, 8, loaded);
+        draw(&mut expected);
+        let mut stored = vec![0; 64];
+        A8::store(expected.get_data(), &mut stored);
+        let mut dt = DrawTarget::<_, A8>::from_backing(8, 8, background.clone());
+        draw(&mut dt);
+        assert_eq!(dt.get_data(), &stored[..]);
+        assert_ne!(dt.get_data(), &background[..]);
+    }
+
+    #[test]
+    fn mask_target() {
+        let mut a = PathBuilder::new();
+        a.rect(0., 0., 3., 4.);
+        let a = a.finish();
+        let mut b = PathBuilder::new();
+        b.rect(1., 0., 3., 4.);
+        let b = b.finish();
+
+        let mut dt = DrawTarget::new_mask(4, 1);
+        dt.fill(&a, &WHITE_SOURCE, &DrawOptions::new());
+        dt.fill_coverage(&b, CoverageOp::Min, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[0, 0xff, 0xff, 0]);
+
+        dt.fill_coverage(&b, CoverageOp::Max, &DrawOptions::new());
+        assert_eq!(dt.get_data(), &[0, 0xff, 0xff, 0xff]);
+
+        dt.fill_coverage(&a, CoverageOp::Multiply, &DrawOptions { alpha: 0.5, ..DrawOptions::new() });
+        assert_eq!(dt.get_data(), &[0, 0x80, 0x80, 0]);
+
+        // the clip limits what changes
+        dt.push_clip_rect(intrect(0, 0, 2, 1));
+        dt.fill_coverage(&a, CoverageOp::Min, &DrawOptions { alpha: 0., ..DrawOptions::new() });
+        dt.pop_clip();
+        assert_eq!(dt.get_data(), &[0, 0, 0x80, 0]);
+
+        // transforms work like they do with other targets
+        let mut mask = DrawTarget::new_mask(4, 4);
+        mask.set_transform(&Transform::scale(2., 2.));
+        mask.fill(&a, &WHITE_SOURCE, &DrawOptions::new());
+        let mask = mask.into_mask();
+        assert_eq!(mask.data[..4], [0xff, 0xff, 0xff, 0xff]);
+
+        let mut dt = DrawTarget::new(4, 4);
+        dt.mask(&WHITE_SOURCE, 0, 0, &mask);
+        assert!(dt.get_data().iter().all(|&p| p == 0xffffffff));
+    }
+
+    fn assert_close(a: &[u32], b: &[u32], tolerance: i32) {
+        for (i, (a, b)) in a.iter().zip(b).enumerate() {
+            for shift in &[0, 8, 16, 24] {
+                let diff = ((a >> shift) & 0xff) as i32 - ((b >> shift) & 0xff) as i32;
+                assert!(diff.abs() <= tolerance, "pixel {}: {:x} vs {:x}", i, a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn high_bit_depth() {
+        fn draw<Backing: AsRef<[F::Pixel]> + AsMut<[F::Pixel]>, F: PixelFormat>(dt: &mut DrawTarget<Backing, F>) {
+            dt.clear(SolidSource::from_unpremultiplied_argb(0xff, 0x20, 0x40, 0x60));
+            let mut pb = PathBuilder::new();
+            pb.move_to(1., 1.);
+            pb.line_to(30., 4.);
+            pb.line_to(10., 30.);
+            let path = pb.finish();
+            let gradient = Source::new_linear_gradient(
+                Gradient {
+                    stops: vec![
+                        GradientStop { position: 0., color: Color::new(0xff, 0xff, 0, 0) },
+                        GradientStop { position: 1., color: Color::new(0x80, 0, 0, 0xff) },
+                    ],
+                },
+                Point::new(0., 0.),
+                Point::new(32., 0.),
+                Spread::Pad,
+            );
+            dt.push_clip(&path);
+            dt.push_layer(0.5);
+            dt.fill_rect(0., 0., 32., 32., &gradient, &DrawOptions::new());
+            dt.pop_layer();
+            dt.pop_clip();
+            dt.fill_rect(16., 16., 16., 16., &Source::Solid(SolidSource::from_unpremultiplied_argb(0x80, 0xff, 0x80, 0)), &DrawOptions::new());
+        }
+
+        let mut expected = DrawTarget::new(32, 32);
+        draw(&mut expected);
+
+        let mut dt = DrawTarget::<_, Rgba16>::with_format(32, 32);
+        draw(&mut dt);
+        assert_close(dt.to_argb32(false).get_data(), expected.get_data(), 3);
+
+        let mut dt = DrawTarget::<_, RgbaF32>::with_format(32, 32);
+        draw(&mut dt);
+        assert_close(dt.to_argb32(false).get_data(), expected.get_data(), 3);
+    }
+
+    #[test]
+    fn high_bit_depth_blend_modes() {
+        let modes = [
+            BlendMode::Dst, BlendMode::Src, BlendMode::Clear, BlendMode::SrcOver, BlendMode::DstOver,
+            BlendMode::SrcIn, BlendMode::DstIn, BlendMode::SrcOut, BlendMode::DstOut, BlendMode::SrcAtop,
+            BlendMode::DstAtop, BlendMode::Xor, BlendMode::Add, BlendMode::Screen, BlendMode::Overlay,
+            BlendMode::Darken, BlendMode::Lighten, BlendMode::ColorDodge, BlendMode::ColorBurn,
+            BlendMode::HardLight, BlendMode::SoftLight, BlendMode::Difference, BlendMode::Exclusion,
+            BlendMode::Multiply, BlendMode::Hue, BlendMode::Saturation, BlendMode::Color, BlendMode::Luminosity,
+        ];
+        // sw_composite's Color mode only matches the spec when both alphas are the same
+        let dst = SolidSource::from_unpremultiplied_argb(0xc0, 0x30, 0x90, 0xe0);
+        let src = Source::Solid(SolidSource::from_unpremultiplied_argb(0xc0, 0xd0, 0x60, 0x20));
+        for &blend_mode in &modes {
+            let options = DrawOptions { blend_mode, ..DrawOptions::new() };
+            let mut expected = DrawTarget::new(2, 1);
+            expected.clear(dst);
+            expected.fill_rect(0., 0., 1., 1., &src, &options);
+
+            let mut dt = DrawTarget::<_, RgbaF32>::with_format(2, 1);
+            dt.clear(dst);
+            dt.fill_rect(0., 0., 1., 1., &src, &options);
+            assert_close(dt.to_argb32(false).get_data(), expected.get_data(), 3);
+        }
+    }
+
+    #[test]
+    fn high_bit_depth_gradient() {
+        // a gradient that only covers 17 values with 8 bits per channel
+        let gradient = Source::new_linear_gradient(
+            Gradient {
+                stops: vec![
+                    GradientStop { position: 0., color: Color::new(0xff, 0, 0, 0) },
+                    GradientStop { position: 1., color: Color::new(0xff, 0x10, 0x10, 0x10) },
+                ],
+            },
+            Point::new(0., 0.),
+            Point::new(256., 0.),
+            Spread::Pad,
+        );
+        let mut dt = DrawTarget::<_, Rgba16>::with_format(256, 8);
+        dt.fill_rect(0., 0., 256., 8., &gradient, &DrawOptions::new());
+        let row = &dt.get_data()[..256];
+        assert!(row.windows(2).all(|w| w[0][0] < w[1][0]));
+
+        // dithering keeps the average of the smooth values
+        let exact: f32 = dt.get_data().iter().map(|p| p[0] as f32 / 65535. * 255.).sum();
+        let dithered = dt.to_argb32(true);
+        let sum: f32 = dithered.get_data().iter().map(|p| ((p >> 16) & 0xff) as f32).sum();
+        assert!((sum - exact).abs() / 2048. < 0.05);
+        let rounded = dt.to_argb32(false);
+        let distinct = |row: &[u32]| row.windows(2).filter(|w| w[0] != w[1]).count();
+        assert_eq!(distinct(&rounded.get_data()[..256]), 16);
+        assert!(distinct(&dithered.get_data()[..256]) > 64);
+    }
+
+    #[test]
+    fn hdr() {
+        let mut dt = DrawTarget::<_, RgbaF32>::with_format(2, 2);
+        let src = Source::SolidF32(SolidSourceF32 { r: 2., g: 1., b: 0.5, a: 1. });
+        dt.fill_rect(0., 0., 2., 2., &src, &DrawOptions::new());
+        dt.fill_rect(0., 0., 1., 1., &src, &DrawOptions { blend_mode: BlendMode::Add, ..DrawOptions::new() });
+        assert_eq!(dt.get_data()[0], [4., 2., 1., 2.]);
+        assert_eq!(dt.get_data()[1], [2., 1., 0.5, 1.]);
+        // converting clamps
+        assert_eq!(dt.to_argb32(false).get_data()[1], 0xffff_ff80);
+
+        let mut dt = DrawTarget::<_, Rgba16>::with_format(1, 1);
+        dt.fill_rect(0., 0., 1., 1., &src, &DrawOptions::new());
+        assert_eq!(dt.get_data()[0], [0xffff, 0xffff, 0x8000, 0xffff]);
+    }
+
+    #[test]
+    fn linear_blending() {
+        // drawing nothing leaves every value the same after decoding and encoding
+        let mut dt = DrawTarget::new(256, 2);
+        for (i, pixel) in dt.get_data_mut().iter_mut().enumerate() {
+            let c = (i % 256) as u32;
+            *pixel = if i < 256 { 0xff00_0000 | c << 16 | c << 8 | c } else { c << 24 | c << 16 };
+        }
+        let before = dt.get_data().to_vec();
+        dt.set_linear_blending(true);
+        dt.fill_rect(0., 0., 256., 2., &WHITE_SOURCE, &DrawOptions { alpha: 0., ..DrawOptions::new() });
+        assert_eq!(dt.get_data(), &before[..]);
+
+        // half black over white is the sRGB encoding of half the light
+        let black = Source::Solid(SolidSource { r: 0, g: 0, b: 0, a: 0xff });
+        let mut dt = DrawTarget::new(3, 1);
+        dt.clear(SolidSource { r: 0xff, g: 0xff, b: 0xff, a: 0xff });
+        dt.set_linear_blending(true);
+        assert!(dt.linear_blending());
+        dt.fill_rect(0., 0., 1., 1., &black, &DrawOptions { alpha: 0.5, ..DrawOptions::new() });
+        // a half covered pixel
+        dt.fill_rect(1., 0., 0.5, 1., &black, &DrawOptions::new());
+        // layers
+        dt.push_layer(0.5);
+        dt.fill_rect(2., 0., 1., 1., &black, &DrawOptions::new());
+        dt.pop_layer();
+        for &pixel in dt.get_data() {
+            assert!(((pixel & 0xff) as i32 - 0xbc).abs() <= 1, "{:x}", pixel);
+        }
+
+        // it can't be changed while there are layers
+        dt.push_layer(1.);
+        assert!(!dt.set_linear_blending(false));
+        assert!(dt.linear_blending());
+        dt.pop_layer();
+
+        dt.set_linear_blending(false);
+        dt.fill_rect(0., 0., 1., 1., &black, &DrawOptions { alpha: 0.5, ..DrawOptions::new() });
+        assert_eq!(dt.get_data()[0] & 0xff, 0x5e);
+
+        // translucent sources are unpremultiplied before they are decoded
+        let white = Source::Solid(SolidSource::from_unpremultiplied_argb(0x80, 0xff, 0xff, 0xff));
+        let mut dt = DrawTarget::new(1, 1);
+        dt.clear(SolidSource { r: 0, g: 0, b: 0, a: 0xff });
+        dt.set_linear_blending(true);
+        dt.fill_rect(0., 0., 1., 1., &white, &DrawOptions::new());
+        let pixel = dt.get_data()[0];
+        assert_eq!(pixel >> 24, 0xff);
+        assert!(((pixel & 0xff) as i32 - 0xbc).abs() <= 1, "{:x}", pixel);
+    }
+
+    #[test]
+    fn gradient_color_spaces() {
+        fn midpoint(from: Color, to: Color, color_space: GradientColorSpace) -> (u32, u32) {
+            let gradient = Source::new_linear_gradient(
+                Gradient {
+                    stops: vec![
+                        GradientStop { position: 0., color: from },
+                        GradientStop { position: 1., color: to },
+                    ],
+                },
+                Point::new(0., 0.),
+                Point::new(256., 0.),
+                Spread::Pad,
+            ).with_gradient_options(GradientOptions { color_space, ..GradientOptions::new() });
+            let mut dt = DrawTarget::new(256, 1);
+            dt.fill_rect(0., 0., 256., 1., &gradient, &DrawOptions::new());
+            // the stops are resampled once when the source is made instead of on every draw
+            match &gradient {
+                Source::GradientWithOptions(with_options) => {
+                    assert_eq!(with_options.resampled.is_some(), color_space != GradientColorSpace::Srgb);
+                    assert_eq!(with_options.options().color_space, color_space);
+                }
+                _ => unreachable!(),
+            }
+            let mut wide = DrawTarget::<_, RgbaF32>::with_format(256, 1);
+            wide.fill_rect(0., 0., 256., 1., &gradient, &DrawOptions::new());
+            (dt.get_data()[128], wide.to_argb32(false).get_data()[128])
+        }
+        let black = Color::new(0xff, 0, 0, 0);
+        let white = Color::new(0xff, 0xff, 0xff, 0xff);
+        let gray = |c: u32| 0xff00_0000 | c << 16 | c << 8 | c;
+
+        let (lut, exact) = midpoint(black, white, GradientColorSpace::Srgb);
+        assert_close(&[lut, exact], &[gray(0x80), gray(0x80)], 1);
+        // half the light
+        let (lut, exact) = midpoint(black, white, GradientColorSpace::LinearSrgb);
+        assert_close(&[lut, exact], &[gray(0xbc), gray(0xbc)], 2);
+        // half the lightness
+        let (lut, exact) = midpoint(black, white, GradientColorSpace::Oklab);
+        assert_close(&[lut, exact], &[gray(0x63), gray(0x63)], 2);
+
+        // red to blue goes through magenta the short way and green the long way
+        let red = Color::new(0xff, 0xff, 0, 0);
+        let blue = Color::new(0xff, 0, 0, 0xff);
+        let green = |c: u32| (c >> 8) & 0xff;
+        let (lut, exact) = midpoint(red, blue, GradientColorSpace::Oklch(HueInterpolation::Shorter));
+        assert_close(&[lut], &[exact], 2);
+        assert!(green(lut) < 0x10 && (lut >> 16) & 0xff > 0x80 && lut & 0xff > 0x80);
+        let (lut, exact) = midpoint(red, blue, GradientColorSpace::Oklch(HueInterpolation::Longer));
+        assert_close(&[lut], &[exact], 2);
+        assert!(green(lut) > 0x80);
+
+        // the default options don't change anything
+        let gradient = Source::new_radial_gradient(
+            Gradient {
+                stops: vec![
+                    GradientStop { position: 0.2, color: red },
+                    GradientStop { position: 0.8, color: blue },
+                ],
+            },
+            Point::new(8., 8.),
+            8.,
+            Spread::Reflect,
+        );
+        let mut expected = DrawTarget::new(16, 16);
+        expected.fill_rect(0., 0., 16., 16., &gradient, &DrawOptions::new());
+        let mut dt = DrawTarget::new(16, 16);
+        dt.fill_rect(0., 0., 16., 16., &gradient.with_gradient_options(GradientOptions::new()), &DrawOptions::new());
+        assert_eq!(dt.get_data(), expected.get_data());
+    }
+
+    #[test]
+    fn dithered_gradient() {
+        let gradients = [
+            Source::new_linear_gradient(
+                Gradient {
+                    stops: vec![
+                        GradientStop { position: 0., color: Color::new(0xff, 0x40, 0x40, 0x40) },
+                        GradientStop { position: 1., color: Color::new(0xff, 0x48, 0x48, 0x48) },
+                    ],
+                },
+                Point::new(0., 0.),
+                Point::new(256., 0.),
+                Spread::Pad,
+            ),
+            Source::new_two_circle_radial_gradient(
+                Gradient {
+                    stops: vec![
+                        GradientStop { position: 0., color: Color::new(0xff, 0x40, 0x40, 0x40) },
+                        GradientStop { position: 1., color: Color::new(0xff, 0x48, 0x48, 0x48) },
+                    ],
+                },
+                Point::new(-10., 0.),
+                10.,
+                Point::new(0., 0.),
+                300.,
+                Spread::Pad,
+            ),
+            Source::new_radial_gradient(
+                Gradient {
+                    stops: vec![
+                        GradientStop { position: 0., color: Color::new(0xff, 0x40, 0x40, 0x40) },
+                        GradientStop { position: 1., color: Color::new(0xff, 0x48, 0x48, 0x48) },
+                    ],
+                },
+                Point::new(-10., 4.),
+                280.,
+                Spread::Pad,
+            ),
+            Source::new_sweep_gradient(
+                Gradient {
+                    stops: vec![
+                        GradientStop { position: 0., color: Color::new(0xff, 0x40, 0x40, 0x40) },
+                        GradientStop { position: 1., color: Color::new(0xff, 0x48, 0x48, 0x48) },
+                    ],
+                },
+                Point::new(128., 20.),
+                0.,
+                360.,
+                Spread::Pad,
+            ),
+        ];
+        for gradient in gradients.iter() {
+            let mut banded = DrawTarget::new(256, 8);
+            banded.fill_rect(0., 0., 256., 8., gradient, &DrawOptions::new());
+            let dithered_gradient = gradient.clone().with_gradient_options(GradientOptions { dither: true, ..GradientOptions::new() });
+            let mut dithered = DrawTarget::new(256, 8);
+            dithered.fill_rect(0., 0., 256., 8., &dithered_gradient, &DrawOptions::new());
+            let mut exact = DrawTarget::<_, RgbaF32>::with_format(256, 8);
+            exact.fill_rect(0., 0., 256., 8., gradient, &DrawOptions::new());
+
+            let changes = |dt: &DrawTarget| dt.get_data().windows(2).filter(|w| w[0] != w[1]).count();
+            assert!(changes(&dithered) > 4 * changes(&banded));
+            // the dither keeps the average of the exact colors
+            let sum: f32 = dithered.get_data().iter().map(|p| (p & 0xff) as f32).sum();
+            let exact_sum: f32 = exact.get_data().iter().map(|p| p[2] * 255.).sum();
+            assert!((sum - exact_sum).abs() / 2048. < 0.05);
+            assert!(dithered.get_data().iter().all(|p| p >> 24 == 0xff));
+
+            // targets that blend in linear light dither when they round to 8 bits
+            let mut linear = DrawTarget::new(256, 8);
+            linear.set_linear_blending(true);
+            linear.fill_rect(0., 0., 256., 8., &dithered_gradient, &DrawOptions::new());
+            assert!(changes(&linear) > 4 * changes(&banded));
+            let sum: f32 = linear.get_data().iter().map(|p| (p & 0xff) as f32).sum();
+            assert!((sum - exact_sum).abs() / 2048. < 0.05);
+        }
+    }
+
+    #[test]
+    fn per_pixel_gradient() {
+        let red = Color::new(0xff, 0xff, 0, 0);
+        let blue = Color::new(0xff, 0, 0, 0xff);
+        // a hard stop that isn't on one of the entries of the lookup table
+        let gradient = Source::new_linear_gradient(
+            Gradient {
+                stops: vec![
+                    GradientStop { position: 0., color: red },
+                    GradientStop { position: 0.3001, color: red },
+                    GradientStop { position: 0.3001, color: blue },
+                    GradientStop { position: 1., color: blue },
+                ],
+            },
+            Point::new(0., 0.),
+            Point::new(1000., 0.),
+            Spread::Pad,
+        );
+        let options = GradientOptions { evaluation: GradientEvaluation::PerPixel, ..GradientOptions::new() };
+        let mut dt = DrawTarget::new(1000, 1);
+        dt.fill_rect(0., 0., 1000., 1., &gradient.clone().with_gradient_options(options), &DrawOptions::new());
+        let (red, blue) = (0xffff0000, 0xff0000ff);
+        assert!(dt.get_data()[..300].iter().all(|&p| p == red));
+        assert!(dt.get_data()[300..].iter().all(|&p| p == blue));
+
+        let mut table = DrawTarget::new(1000, 1);
+        table.fill_rect(0., 0., 1000., 1., &gradient, &DrawOptions::new());
+        assert!(table.get_data() != dt.get_data());
+
+        // smooth parts match the lookup table
+        let gradient = Source::new_sweep_gradient(
+            Gradient {
+                stops: vec![
+                    GradientStop { position: 0., color: Color::new(0xff, 0xff, 0, 0) },
+                    GradientStop { position: 0.5, color: Color::new(0x80, 0, 0xff, 0) },
+                    GradientStop { position: 1., color: Color::new(0xff, 0, 0, 0xff) },
+                ],
+            },
+            Point::new(16., 16.),
+            0.,
+            360.,
+            Spread::Pad,
+        );
+        let mut dt = DrawTarget::new(32, 32);
+        dt.fill_rect(0., 0., 32., 32., &gradient.clone().with_gradient_options(options), &DrawOptions::new());
+        let mut table = DrawTarget::new(32, 32);
+        table.fill_rect(0., 0., 32., 32., &gradient, &DrawOptions::new());
+        assert_close(dt.get_data(), table.get_data(), 4);
+    }
+
+    #[test]
+    fn mesh_gradient() {
+        let (red, green, blue, white) = (Color::new(0xff, 0xff, 0, 0), Color::new(0xff, 0, 0xff, 0),
+                                         Color::new(0xff, 0, 0, 0xff), Color::new(0xff, 0xff, 0xff, 0xff));
+        let mut mesh = MeshGradient::new();
+        mesh.add_triangle(MeshVertex::new(Point::new(0., 0.), red), MeshVertex::new(Point::new(64., 0.), green),
+                          MeshVertex::new(Point::new(64., 64.), blue));
+        mesh.add_triangle(MeshVertex::new(Point::new(0., 0.), red), MeshVertex::new(Point::new(64., 64.), blue),
+                          MeshVertex::new(Point::new(0., 64.), white));
+        let mut dt = DrawTarget::new(80, 80);
+        dt.fill_rect(0., 0., 80., 80., &Source::new_mesh_gradient(&mesh), &DrawOptions::new());
+        for (i, &pixel) in dt.get_data().iter().enumerate() {
+            let (x, y) = (i % 80, i / 80);
+            // no seams inside of the mesh and nothing outside of it
+            if x < 64 && y < 64 {
+                assert_eq!(pixel >> 24, 0xff, "{} {}", x, y);
+            } else {
+                assert_eq!(pixel, 0, "{} {}", x, y);
+            }
+        }
+        assert_close(&[dt.get_data()[0]], &[0xffff0000], 4);
+        assert_close(&[dt.get_data()[63]], &[0xff00ff00], 4);
+        assert_close(&[dt.get_data()[63 * 80]], &[0xffffffff], 4);
+
+        // a Coons patch with straight sides is the same as the tensor-product patch with
+        // evenly spaced control points
+        let p = |i: usize, j: usize| Point::new(i as f32 * 64. / 3., j as f32 * 64. / 3.);
+        let boundary = [p(0, 0), p(0, 1), p(0, 2), p(0, 3), p(1, 3), p(2, 3), p(3, 3), p(3, 2), p(3, 1), p(3, 0), p(2, 0), p(1, 0)];
+        let colors = [red, blue, white, green];
+        let coons = MeshPatch::new_coons(boundary, colors);
+        let points = [[p(0, 0), p(0, 1), p(0, 2), p(0, 3)], [p(1, 0), p(1, 1), p(1, 2), p(1, 3)],
+                      [p(2, 0), p(2, 1), p(2, 2), p(2, 3)], [p(3, 0), p(3, 1), p(3, 2), p(3, 3)]];
+        let tensor = MeshPatch::new_tensor(points, colors);
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((coons.points[i][j] - tensor.points[i][j]).length() < 1e-3);
+            }
+        }
+        assert!((coons.point_at(0.25, 0.5) - Point::new(16., 32.)).length() < 1e-3);
+
+        let mut mesh = MeshGradient::new();
+        mesh.add_patch(coons);
+        let mut dt = DrawTarget::new(64, 64);
+        dt.fill_rect(0., 0., 64., 64., &Source::new_mesh_gradient(&mesh), &DrawOptions::new());
+        assert_close(&[dt.get_data()[0]], &[0xffff0000], 4);
+        assert_close(&[dt.get_data()[63]], &[0xff00ff00], 4);
+        assert_close(&[dt.get_data()[63 * 64]], &[0xff0000ff], 4);
+        assert_close(&[dt.get_data()[32 * 64 + 32]], &[0xff808080], 4);
+
+        // clips and blending apply like for other sources
+        let mut clipped = DrawTarget::new(64, 64);
+        clipped.push_clip_rect(intrect(0, 0, 32, 64));
+        clipped.fill_rect(0., 0., 64., 64., &Source::new_mesh_gradient(&mesh), &DrawOptions { alpha: 0.5, ..DrawOptions::new() });
+        for (i, &pixel) in clipped.get_data().iter().enumerate() {
+            if i % 64 < 32 {
+                assert_close(&[pixel], &[dt.get_data()[i] >> 1 & 0x7f7f7f7f], 1);
+            } else {
+                assert_eq!(pixel, 0);
+            }
+        }
+
+        // wide targets shade with the same geometry
+        let mut wide = DrawTarget::<_, RgbaF32>::with_format(64, 64);
+        wide.fill_rect(0., 0., 64., 64., &Source::new_mesh_gradient(&mesh), &DrawOptions::new());
+        assert_close(wide.to_argb32(false).get_data(), dt.get_data(), 1);
+
+        // a triangle that spans many bands of rows and starts above the target is found for
+        // every row and a later, small one stays on top of it
+        let mut mesh = MeshGradient::new();
+        mesh.add_triangle(MeshVertex::new(Point::new(0., -1000.), red), MeshVertex::new(Point::new(1000., -1000.), red),
+                          MeshVertex::new(Point::new(0., 1000.), red));
+        mesh.add_triangle(MeshVertex::new(Point::new(0., 56.), blue), MeshVertex::new(Point::new(8., 56.), blue),
+                          MeshVertex::new(Point::new(0., 64.), blue));
+        let mut dt = DrawTarget::new(64, 64);
+        dt.fill_rect(0., 0., 64., 64., &Source::new_mesh_gradient(&mesh), &DrawOptions::new());
+        for (i, &pixel) in dt.get_data().iter().enumerate() {
+            let (x, y) = (i % 64, i / 64);
+            let expected = if x + y <= 63 && y >= 56 { 0xff0000ff } else { 0xffff0000 };
+            assert_eq!(pixel, expected, "{} {}", x, y);
+        }
+    }
+
+    #[test]
+    fn mesh_patch_boundary() {
+        // a large patch that is mostly outside of the target. Its first side is a parabola with
+        // its vertex at 64, 32 and a radius of curvature of 1000 there.
+        let white = Color::new(0xff, 0xff, 0xff, 0xff);
+        let (start, control, end) = (Point::new(-4936., 12532.), Point::new(64., -12468.), Point::new(5064., 12532.));
+        let boundary = [
+            start, start.lerp(control, 2. / 3.), end.lerp(control, 2. / 3.), end,
+            Point::new(5064., 15000.), Point::new(5064., 17500.), Point::new(5064., 20000.),
+            Point::new(1731., 20000.), Point::new(-1603., 20000.), Point::new(-4936., 20000.),
+            Point::new(-4936., 17500.), Point::new(-4936., 15000.),
+        ];
+        let coons = MeshPatch::new_coons(boundary, [white; 4]);
+        let mut mesh = MeshGradient::new();
+        mesh.add_patch(coons);
+        let mut dt = DrawTarget::new(128, 128);
+        dt.fill_rect(0., 0., 128., 128., &Source::new_mesh_gradient(&mesh), &DrawOptions::new());
+        let pixel = |p: Point| dt.get_data()[p.y as usize * 128 + p.x as usize];
+
+        // the pixels just inside of the side are drawn and the ones just outside aren't
+        let mut checked = 0;
+        for i in 0..=20000 {
+            let v = i as f32 / 20000.;
+            let p = coons.point_at(0., v);
+            let inward = (coons.point_at(0.001, v) - p).normalize() * 1.5;
+            let (inside, outside) = (p + inward, p - inward);
+            if [inside, outside].iter().all(|p| p.x >= 0. && p.y >= 0. && p.x < 128. && p.y < 128.) {
+                assert_eq!(pixel(inside), 0xffffffff, "{:?}", p);
+                assert_eq!(pixel(outside), 0, "{:?}", p);
+                checked += 1;
+            }
+        }
+        assert!(checked > 100);
+    }
+
+    #[test]
+    fn blend_rect_stays_in_span() {
+        // blending a rect without a mask only touches the pixels of the rect
+        let mut dt = DrawTarget::new(4, 2);
+        dt.clear(SolidSource::from_unpremultiplied_argb(0xff, 0, 0, 0xff));
+        let mut options = DrawOptions::new();
+        options.blend_mode = BlendMode::Src;
+        dt.fill_rect(0., 0., 2., 2., &Source::Solid(SolidSource::from_unpremultiplied_argb(0xff, 0xff, 0, 0)), &options);
+        assert_eq!(dt.get_data(), &[0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff,
+                                    0xffff0000, 0xffff0000, 0xff0000ff, 0xff0000ff]);
+    }
 }
@@ -0,0 +1,302 @@
+// Shading and blending with f32 components for formats with more precision than ARGB32
+// and for blending in linear light. Colors are premultiplied [r, g, b, a] and aren't
+// clamped so that HDR content survives until it is stored.
+
+use crate::blitter::{choose_shader, ShaderStorage};
+use crate::color::SrgbTables;
+use crate::draw_target::{BlendMode, Source};
+use crate::format::{argb32_to_f32, f32_to_argb32};
+use crate::gradient::GradientF32;
+use crate::mesh::MeshShader;
+use crate::{IntRect, Transform};
+
+pub trait WideShader {
+    fn shade_span_wide(&self, x: i32, y: i32, dest: &mut [[f32; 4]]);
+}
+
+pub struct WideSolidShader {
+    pub color: [f32; 4],
+}
+
+impl WideShader for WideSolidShader {
+    fn shade_span_wide(&self, _x: i32, _y: i32, dest: &mut [[f32; 4]]) {
+        for dest in dest {
+            *dest = self.color;
+        }
+    }
+}
+
+impl WideShader for GradientF32 {
+    fn shade_span_wide(&self, x: i32, y: i32, dest: &mut [[f32; 4]]) {
+        self.shade_span(x, y, dest);
+    }
+}
+
+/// Runs one of the ARGB32 shaders and converts the result. Images only have 8 bits
+/// per channel so there's nothing to gain from shading them with more precision.
+pub struct NarrowShader<'a, 'b>(ShaderStorage<'a, 'b>);
+
+impl<'a, 'b> WideShader for NarrowShader<'a, 'b> {
+    fn shade_span_wide(&self, x: i32, y: i32, dest: &mut [[f32; 4]]) {
+        let mut tmp = [0; 64];
+        for (i, dest) in dest.chunks_mut(tmp.len()).enumerate() {
+            self.0.shader().shade_span(x + (i * tmp.len()) as i32, y, &mut tmp, dest.len());
+            for (dest, src) in dest.iter_mut().zip(tmp.iter()) {
+                *dest = argb32_to_f32(*src);
+            }
+        }
+    }
+}
+
+/// Decodes the sRGB output of another shader to linear light
+pub struct DecodeShader<'a> {
+    pub shader: &'a dyn WideShader,
+    pub tables: &'a SrgbTables,
+}
+
+impl<'a> WideShader for DecodeShader<'a> {
+    fn shade_span_wide(&self, x: i32, y: i32, dest: &mut [[f32; 4]]) {
+        self.shader.shade_span_wide(x, y, dest);
+        dest.iter_mut().for_each(|pixel| self.tables.decode_color(pixel));
+    }
+}
+
+/// Reads the pixels of a popped layer with `opacity` applied
+pub struct LayerShader<'a> {
+    pub buf: &'a [[f32; 4]],
+    pub rect: IntRect,
+    pub opacity: f32,
+}
+
+impl<'a> WideShader for LayerShader<'a> {
+    fn shade_span_wide(&self, x: i32, y: i32, dest: &mut [[f32; 4]]) {
+        let start = ((y - self.rect.min.y) * self.rect.size().width + x - self.rect.min.x) as usize;
+        for (dest, src) in dest.iter_mut().zip(&self.buf[start..]) {
+            for i in 0..4 {
+                dest[i] = src[i] * self.opacity;
+            }
+        }
+    }
+}
+
+pub enum WideShaderStorage<'a, 'b> {
+    None,
+    Solid(WideSolidShader),
+    Gradient(GradientF32),
+    Mesh(MeshShader),
+    Narrow(NarrowShader<'a, 'b>),
+}
+
+pub fn choose_wide_shader<'a, 'b, 'c>(ti: &Transform, src: &'b Source<'c>, alpha: f32, rect: IntRect, shader_storage: &'a mut WideShaderStorage<'b, 'c>) -> &'a dyn WideShader {
+    let alpha = alpha.clamp(0., 1.);
+    *shader_storage = match src {
+        Source::Solid(c) => {
+            let color = argb32_to_f32(c.to_u32());
+            WideShaderStorage::Solid(WideSolidShader { color: color.map(|c| c * alpha) })
+        }
+        Source::SolidF32(c) => {
+            WideShaderStorage::Solid(WideSolidShader { color: [c.r * alpha, c.g * alpha, c.b * alpha, c.a * alpha] })
+        }
+        Source::MeshGradient(mesh, transform) => WideShaderStorage::Mesh(MeshShader::new(mesh, &ti.then(transform), alpha, rect)),
+        _ => match GradientF32::new(src, ti, alpha) {
+            Some(gradient) => WideShaderStorage::Gradient(gradient),
+            None => {
+                let mut storage = ShaderStorage::None;
+                choose_shader(ti, src, alpha, rect, &mut storage);
+                WideShaderStorage::Narrow(NarrowShader(storage))
+            }
+        },
+    };
+
+    match shader_storage {
+        WideShaderStorage::None => unreachable!(),
+        WideShaderStorage::Solid(s) => s,
+        WideShaderStorage::Gradient(s) => s,
+        WideShaderStorage::Mesh(s) => s,
+        WideShaderStorage::Narrow(s) => s,
+    }
+}
+
+/// Blends `src` over `dst` with `coverage` between 0 and 1
+pub fn blend_with_coverage(src: [f32; 4], dst: &mut [f32; 4], coverage: f32, blend: fn([f32; 4], [f32; 4]) -> [f32; 4]) {
+    let result = blend(src, *dst);
+    for i in 0..4 {
+        dst[i] += (result[i] - dst[i]) * coverage;
+    }
+}
+
+fn porter_duff(src: [f32; 4], dst: [f32; 4], src_factor: f32, dst_factor: f32) -> [f32; 4] {
+    let mut result = [0.; 4];
+    for i in 0..4 {
+        result[i] = src[i] * src_factor + dst[i] * dst_factor;
+    }
+    result
+}
+
+fn unpremultiply(color: [f32; 4]) -> [f32; 3] {
+    if color[3] > 0. {
+        [color[0] / color[3], color[1] / color[3], color[2] / color[3]]
+    } else {
+        [0.; 3]
+    }
+}
+
+// Composites using the blend function `f` which takes and returns unpremultiplied colors
+// as described in the Compositing and Blending spec.
+fn blend_colors(src: [f32; 4], dst: [f32; 4], f: impl Fn([f32; 3], [f32; 3]) -> [f32; 3]) -> [f32; 4] {
+    let (sa, da) = (src[3], dst[3]);
+    let blended = f(unpremultiply(dst), unpremultiply(src));
+    let mut result = [0.; 4];
+    for i in 0..3 {
+        result[i] = src[i] * (1. - da) + dst[i] * (1. - sa) + sa * da * blended[i];
+    }
+    result[3] = sa + da - sa * da;
+    result
+}
+
+fn separable(src: [f32; 4], dst: [f32; 4], f: fn(f32, f32) -> f32) -> [f32; 4] {
+    blend_colors(src, dst, |cb, cs| [f(cb[0], cs[0]), f(cb[1], cs[1]), f(cb[2], cs[2])])
+}
+
+fn multiply(cb: f32, cs: f32) -> f32 {
+    cb * cs
+}
+
+fn screen(cb: f32, cs: f32) -> f32 {
+    cb + cs - cb * cs
+}
+
+fn hard_light(cb: f32, cs: f32) -> f32 {
+    if cs <= 0.5 {
+        multiply(cb, 2. * cs)
+    } else {
+        screen(cb, 2. * cs - 1.)
+    }
+}
+
+fn color_dodge(cb: f32, cs: f32) -> f32 {
+    if cb == 0. {
+        0.
+    } else if cs >= 1. {
+        1.
+    } else {
+        (cb / (1. - cs)).min(1.)
+    }
+}
+
+fn color_burn(cb: f32, cs: f32) -> f32 {
+    if cb >= 1. {
+        1.
+    } else if cs <= 0. {
+        0.
+    } else {
+        1. - ((1. - cb) / cs).min(1.)
+    }
+}
+
+fn soft_light(cb: f32, cs: f32) -> f32 {
+    if cs <= 0.5 {
+        cb - (1. - 2. * cs) * cb * (1. - cb)
+    } else {
+        let d = if cb <= 0.25 { ((16. * cb - 12.) * cb + 4.) * cb } else { cb.sqrt() };
+        cb + (2. * cs - 1.) * (d - cb)
+    }
+}
+
+fn lum(c: [f32; 3]) -> f32 {
+    0.3 * c[0] + 0.59 * c[1] + 0.11 * c[2]
+}
+
+fn clip_color(c: [f32; 3]) -> [f32; 3] {
+    let l = lum(c);
+    let n = c[0].min(c[1]).min(c[2]);
+    let x = c[0].max(c[1]).max(c[2]);
+    let mut c = c;
+    for c in &mut c {
+        if n < 0. {
+            *c = l + (*c - l) * l / (l - n);
+        }
+        if x > 1. {
+            *c = l + (*c - l) * (1. - l) / (x - l);
+        }
+    }
+    c
+}
+
+fn set_lum(c: [f32; 3], l: f32) -> [f32; 3] {
+    let d = l - lum(c);
+    clip_color([c[0] + d, c[1] + d, c[2] + d])
+}
+
+fn sat(c: [f32; 3]) -> f32 {
+    c[0].max(c[1]).max(c[2]) - c[0].min(c[1]).min(c[2])
+}
+
+fn set_sat(c: [f32; 3], s: f32) -> [f32; 3] {
+    let max = c[0].max(c[1]).max(c[2]);
+    let min = c[0].min(c[1]).min(c[2]);
+    if max > min {
+        c.map(|c| (c - min) * s / (max - min))
+    } else {
+        [0.; 3]
+    }
+}
+
+/// Returns a function that blends a source color with a destination color using `mode`
+pub fn build_blend_proc_f32(mode: BlendMode) -> fn([f32; 4], [f32; 4]) -> [f32; 4] {
+    match mode {
+        BlendMode::Dst => |_, d| d,
+        BlendMode::Src => |s, _| s,
+        BlendMode::Clear => |_, _| [0.; 4],
+        BlendMode::SrcOver => |s, d| porter_duff(s, d, 1., 1. - s[3]),
+        BlendMode::DstOver => |s, d| porter_duff(s, d, 1. - d[3], 1.),
+        BlendMode::SrcIn => |s, d| porter_duff(s, d, d[3], 0.),
+        BlendMode::DstIn => |s, d| porter_duff(s, d, 0., s[3]),
+        BlendMode::SrcOut => |s, d| porter_duff(s, d, 1. - d[3], 0.),
+        BlendMode::DstOut => |s, d| porter_duff(s, d, 0., 1. - s[3]),
+        BlendMode::SrcAtop => |s, d| porter_duff(s, d, d[3], 1. - s[3]),
+        BlendMode::DstAtop => |s, d| porter_duff(s, d, 1. - d[3], s[3]),
+        BlendMode::Xor => |s, d| porter_duff(s, d, 1. - d[3], 1. - s[3]),
+        BlendMode::Add => |s, d| porter_duff(s, d, 1., 1.),
+        BlendMode::Screen => |s, d| separable(s, d, screen),
+        BlendMode::Overlay => |s, d| separable(s, d, |cb, cs| hard_light(cs, cb)),
+        BlendMode::Darken => |s, d| separable(s, d, f32::min),
+        BlendMode::Lighten => |s, d| separable(s, d, f32::max),
+        BlendMode::ColorDodge => |s, d| separable(s, d, color_dodge),
+        BlendMode::ColorBurn => |s, d| separable(s, d, color_burn),
+        BlendMode::HardLight => |s, d| separable(s, d, hard_light),
+        BlendMode::SoftLight => |s, d| separable(s, d, soft_light),
+        BlendMode::Difference => |s, d| separable(s, d, |cb, cs| (cb - cs).abs()),
+        BlendMode::Exclusion => |s, d| separable(s, d, |cb, cs| cb + cs - 2. * cb * cs),
+        BlendMode::Multiply => |s, d| separable(s, d, multiply),
+        BlendMode::Hue => |s, d| blend_colors(s, d, |cb, cs| set_lum(set_sat(cs, sat(cb)), lum(cb))),
+        BlendMode::Saturation => |s, d| blend_colors(s, d, |cb, cs| set_lum(set_sat(cb, sat(cs)), lum(cb))),
+        BlendMode::Color => |s, d| blend_colors(s, d, |cb, cs| set_lum(cs, lum(cb))),
+        BlendMode::Luminosity => |s, d| blend_colors(s, d, |cb, cs| set_lum(cb, lum(cs))),
+    }
+}
+
+// An 8x8 Bayer matrix for ordered dithering
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Returns the rounding offset between 0 and 1 that dithers the pixel at `x`, `y`
+pub fn dither_offset(x: i32, y: i32) -> f32 {
+    (BAYER[(y & 7) as usize][(x & 7) as usize] as f32 + 0.5) / 64.
+}
+
+/// Converts a row of pixels starting at `x`, `y` to ARGB32, optionally dithering them
+pub fn row_to_argb32(src: &[[f32; 4]], dst: &mut [u32], x: i32, y: i32, dither: bool) {
+    for (i, (dst, src)) in dst.iter_mut().zip(src).enumerate() {
+        let offset = if dither { dither_offset(x + i as i32, y) } else { 0.5 };
+        *dst = f32_to_argb32(*src, offset);
+    }
+}